@@ -0,0 +1,70 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, Index, Member};
+
+/// Implements `dep_tree::Dependable` from a `#[dep_id]` field holding a
+/// `DepId` and any number of `#[deps]` fields iterable by reference over
+/// `DepId`s. Without a `#[deps]` field the unit has no dependencies.
+#[proc_macro_derive(Dependable, attributes(dep_id, deps))]
+pub fn derive_dependable(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+  let fields = match &input.data {
+    Data::Struct(data) => &data.fields,
+    _ => return Err(Error::new(input.span(), "`Dependable` can only be derived for structs")),
+  };
+
+  let mut dep_id = None;
+  let mut deps = Vec::new();
+  for (index, field) in fields.iter().enumerate() {
+    let member = match &field.ident {
+      Some(ident) => Member::Named(ident.clone()),
+      None => Member::Unnamed(Index::from(index)),
+    };
+    for attr in &field.attrs {
+      if attr.path().is_ident("dep_id") {
+        if dep_id.is_some() {
+          return Err(Error::new(attr.span(), "more than one `#[dep_id]` field"));
+        }
+        dep_id = Some(member.clone());
+      } else if attr.path().is_ident("deps") {
+        deps.push(member.clone());
+      }
+    }
+  }
+  let Some(dep_id) = dep_id else {
+    let span = match fields {
+      Fields::Unit => input.ident.span(),
+      _ => fields.span(),
+    };
+    return Err(Error::new(span, "missing a `#[dep_id]` field"));
+  };
+
+  let deps_body = if deps.is_empty() {
+    quote!(::std::vec::Vec::new())
+  } else {
+    quote! {
+      let mut deps = ::std::vec::Vec::new();
+      #(deps.extend(::std::iter::IntoIterator::into_iter(&self.#deps).copied());)*
+      deps
+    }
+  };
+
+  let name = &input.ident;
+  let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+  Ok(quote! {
+    impl #impl_generics ::dep_tree::Dependable for #name #ty_generics #where_clause {
+      fn dep_id(&self) -> ::dep_tree::DepId {
+        self.#dep_id
+      }
+
+      fn deps(&self) -> ::std::vec::Vec<::dep_tree::DepId> {
+        #deps_body
+      }
+    }
+  })
+}
@@ -0,0 +1,612 @@
+use std::{
+  cmp::Reverse,
+  collections::{btree_map::Entry, BTreeMap, BTreeSet, VecDeque},
+};
+
+use crate::{bfs, closure_sizes, DepId, DepTree, DepTreeBuilder};
+
+/// Two dependency paths that split at `diverging` and meet again at
+/// `reconverging` without sharing any unit in between.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diamond {
+  /// Last unit the two paths share before splitting.
+  pub diverging: DepId,
+  /// Unit where the paths meet again.
+  pub reconverging: DepId,
+  /// Both paths, from `diverging` down to `reconverging`.
+  pub paths: [Vec<DepId>; 2],
+}
+
+/// Direct edge `from -> to` already implied by the longer `path`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedundantEdge {
+  pub from: DepId,
+  pub to: DepId,
+  /// Shortest path from `from` to `to` avoiding the direct edge.
+  pub path: Vec<DepId>,
+}
+
+/// Edge suggested for removal to make a cyclic graph acyclic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeedbackEdge {
+  pub from: DepId,
+  pub to: DepId,
+  /// Cycles through this edge, each listed from its smallest unit without
+  /// repeating it at the end.
+  pub cycles: Vec<Vec<DepId>>,
+}
+
+/// Version of `id` that every dependent of its duplicate versions accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DedupSuggestion {
+  pub id: u64,
+  /// Existing version to keep.
+  pub version: usize,
+  /// Other versions that would lose all of their dependents.
+  pub replaced: Vec<usize>,
+  /// Edges `(dependent, current target)` to point at `(id, version)` instead.
+  pub rewrites: Vec<(DepId, DepId)>,
+  /// Edges that would need a rewrite too but start or end at a pinned unit,
+  /// so they are left as they are.
+  pub held: Vec<(DepId, DepId)>,
+}
+
+impl DepTreeBuilder {
+  /// Suggests a small set of edges whose removal leaves the graph acyclic.
+  /// Greedily cuts the edge shared by the most shortest cycles until none are
+  /// left, so the set is not guaranteed to be minimum. Empty when the graph is
+  /// already acyclic.
+  pub fn feedback_edges(&self) -> Vec<FeedbackEdge> {
+    let mut graph = BTreeMap::<DepId, BTreeSet<DepId>>::new();
+    for (&id, deps) in self.inner.try_borrow().unwrap().iter() {
+      graph.entry(id).or_default().extend(deps.iter().copied());
+    }
+
+    let mut suggestions = Vec::new();
+    loop {
+      let cycles = graph
+        .keys()
+        .filter_map(|&start| shortest_cycle(&graph, start))
+        .collect::<BTreeSet<_>>();
+      let mut counts = BTreeMap::<(DepId, DepId), usize>::new();
+      for cycle in &cycles {
+        for edge in cycle_edges(cycle) {
+          *counts.entry(edge).or_default() += 1;
+        }
+      }
+      let Some((&(from, to), _)) = counts.iter().max_by_key(|(edge, &count)| (count, Reverse(**edge))) else {
+        break;
+      };
+      graph.get_mut(&from).unwrap().remove(&to);
+      let cycles = cycles
+        .into_iter()
+        .filter(|cycle| cycle_edges(cycle).any(|edge| edge == (from, to)))
+        .collect();
+      suggestions.push(FeedbackEdge { from, to, cycles });
+    }
+    suggestions
+  }
+}
+
+impl DepTreeBuilder {
+  /// Every elementary cycle of the graph so far, at most `limit` of them, each
+  /// listed from its smallest unit without repeating it at the end.
+  /// Self-dependencies count as cycles of one unit. Uses Johnson's algorithm,
+  /// so the time spent grows with the number of cycles found rather than the
+  /// number of paths.
+  pub fn cycles(&self, limit: usize) -> Vec<Vec<DepId>> {
+    let mut graph = BTreeMap::<DepId, BTreeSet<DepId>>::new();
+    for (&id, deps) in self.inner.try_borrow().unwrap().iter() {
+      graph.entry(id).or_default().extend(deps.iter().copied());
+    }
+    let mut search = Johnson {
+      graph: &graph,
+      start: (0, 0),
+      limit,
+      stack: Vec::new(),
+      blocked: BTreeSet::new(),
+      blocking: BTreeMap::new(),
+      cycles: Vec::new(),
+    };
+    for &start in graph.keys() {
+      if search.cycles.len() >= limit {
+        break;
+      }
+      search.start = start;
+      search.blocked.clear();
+      search.blocking.clear();
+      search.circuit(start);
+    }
+    search.cycles
+  }
+}
+
+/// State of Johnson's cycle search from one start unit at a time, looking
+/// only at units not smaller than the start.
+struct Johnson<'a> {
+  graph: &'a BTreeMap<DepId, BTreeSet<DepId>>,
+  start: DepId,
+  limit: usize,
+  stack: Vec<DepId>,
+  blocked: BTreeSet<DepId>,
+  /// Units to unblock once the key unit is unblocked.
+  blocking: BTreeMap<DepId, BTreeSet<DepId>>,
+  cycles: Vec<Vec<DepId>>,
+}
+
+impl Johnson<'_> {
+  /// Whether a cycle through the start was found from `id`.
+  fn circuit(&mut self, id: DepId) -> bool {
+    let (graph, start) = (self.graph, self.start);
+    let mut found = false;
+    self.stack.push(id);
+    self.blocked.insert(id);
+    for &dep in graph.get(&id).into_iter().flatten().filter(|&&dep| dep >= start) {
+      if self.cycles.len() >= self.limit {
+        break;
+      }
+      if dep == start {
+        self.cycles.push(self.stack.clone());
+        found = true;
+      } else if !self.blocked.contains(&dep) && self.circuit(dep) {
+        found = true;
+      }
+    }
+    if found {
+      self.unblock(id);
+    } else {
+      for &dep in graph.get(&id).into_iter().flatten().filter(|&&dep| dep >= start) {
+        self.blocking.entry(dep).or_default().insert(id);
+      }
+    }
+    self.stack.pop();
+    found
+  }
+
+  fn unblock(&mut self, id: DepId) {
+    self.blocked.remove(&id);
+    for unit in self.blocking.remove(&id).unwrap_or_default() {
+      if self.blocked.contains(&unit) {
+        self.unblock(unit);
+      }
+    }
+  }
+}
+
+/// Shortest cycle through `start`, rotated to begin at its smallest unit.
+fn shortest_cycle(graph: &BTreeMap<DepId, BTreeSet<DepId>>, start: DepId) -> Option<Vec<DepId>> {
+  let mut parent = BTreeMap::new();
+  let mut queue = VecDeque::from([start]);
+  'search: while let Some(id) = queue.pop_front() {
+    for &dep in graph.get(&id).into_iter().flatten() {
+      if let Entry::Vacant(entry) = parent.entry(dep) {
+        entry.insert(id);
+        if dep == start {
+          break 'search;
+        }
+        queue.push_back(dep);
+      }
+    }
+  }
+  let mut cycle = vec![*parent.get(&start)?];
+  while *cycle.last().unwrap() != start {
+    cycle.push(parent[cycle.last().unwrap()]);
+  }
+  cycle.reverse();
+  let smallest = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap();
+  cycle.rotate_left(smallest);
+  Some(cycle)
+}
+
+fn cycle_edges(cycle: &[DepId]) -> impl Iterator<Item = (DepId, DepId)> + '_ {
+  cycle.iter().zip(cycle.iter().cycle().skip(1)).map(|(&a, &b)| (a, b))
+}
+
+/// Per-unit figures precomputed for `DepTree::rank_by`.
+#[derive(Clone, Debug, Default)]
+pub struct RankContext {
+  dependencies: BTreeMap<DepId, usize>,
+  dependents: BTreeMap<DepId, usize>,
+  transitive_dependencies: BTreeMap<DepId, usize>,
+  transitive_dependents: BTreeMap<DepId, usize>,
+  levels: BTreeMap<DepId, usize>,
+  depths: BTreeMap<DepId, usize>,
+}
+
+impl RankContext {
+  fn new(tree: &DepTree) -> Self {
+    let reverse = tree.reverse_map();
+    let mut order = Vec::new();
+    let mut visited = BTreeSet::new();
+    for &id in reverse.keys() {
+      tree.post_order(id, &mut visited, &mut order);
+    }
+    let mut depths = reverse.keys().map(|&id| (id, 0)).collect::<BTreeMap<_, _>>();
+    for &id in order.iter().rev() {
+      let depth = depths[&id];
+      for dep in tree.direct(id) {
+        let entry = depths.get_mut(dep).unwrap();
+        *entry = (*entry).max(depth + 1);
+      }
+    }
+    Self {
+      dependencies: reverse.keys().map(|&id| (id, tree.direct(id).len())).collect(),
+      dependents: reverse.iter().map(|(&id, dependents)| (id, dependents.len())).collect(),
+      transitive_dependencies: closure_sizes(&tree.inner),
+      transitive_dependents: closure_sizes(&reverse),
+      levels: tree.levels(),
+      depths,
+    }
+  }
+
+  /// Direct dependencies of `id`.
+  pub fn dependencies(&self, id: DepId) -> usize {
+    self.dependencies.get(&id).copied().unwrap_or(0)
+  }
+
+  /// Direct dependents of `id`.
+  pub fn dependents(&self, id: DepId) -> usize {
+    self.dependents.get(&id).copied().unwrap_or(0)
+  }
+
+  /// Distinct transitive dependencies of `id`.
+  pub fn transitive_dependencies(&self, id: DepId) -> usize {
+    self.transitive_dependencies.get(&id).copied().unwrap_or(0)
+  }
+
+  /// Distinct transitive dependents of `id`.
+  pub fn transitive_dependents(&self, id: DepId) -> usize {
+    self.transitive_dependents.get(&id).copied().unwrap_or(0)
+  }
+
+  /// Longest path from `id` down to a leaf, leaves being `0`.
+  pub fn level(&self, id: DepId) -> usize {
+    self.levels.get(&id).copied().unwrap_or(0)
+  }
+
+  /// Longest path from a root down to `id`, roots being `0`.
+  pub fn depth(&self, id: DepId) -> usize {
+    self.depths.get(&id).copied().unwrap_or(0)
+  }
+}
+
+/// Exponents of the two factors of `DepTree::churn_risk`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChurnWeights {
+  pub dependents: f64,
+  pub depth: f64,
+}
+
+impl Default for ChurnWeights {
+  fn default() -> Self {
+    Self { dependents: 1.0, depth: 1.0 }
+  }
+}
+
+impl DepTree {
+  /// Units to rebuild when a unit changes, for every unit: the unit itself
+  /// plus all of its transitive dependents. Computed in one pass.
+  pub fn impact_sizes(&self) -> BTreeMap<DepId, usize> {
+    closure_sizes(&self.reverse_map()).into_iter().map(|(id, size)| (id, size + 1)).collect()
+  }
+
+  /// Every unit scored by `transitive_dependents^w.dependents /
+  /// (depth + 1)^w.depth`, riskiest first: changes to widely used units close
+  /// to the roots reach the most of the graph the soonest.
+  pub fn churn_risk(&self, weights: ChurnWeights) -> Vec<(DepId, f64)> {
+    self.rank_by(|id, context| {
+      let dependents = context.transitive_dependents(id) as f64;
+      let depth = context.depth(id) as f64 + 1.0;
+      dependents.powf(weights.dependents) / depth.powf(weights.depth)
+    })
+  }
+
+  /// Every unit scored by `score`, highest first and ties broken by ascending
+  /// `DepId`.
+  pub fn rank_by(&self, score: impl Fn(DepId, &RankContext) -> f64) -> Vec<(DepId, f64)> {
+    let context = RankContext::new(self);
+    let mut scores = context.dependents.keys().map(|&id| (id, score(id, &context))).collect::<Vec<_>>();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    scores
+  }
+
+  /// Number of units on each level, leaves first. Units on one level never
+  /// depend on each other, so each width is a worker count that can be kept
+  /// busy.
+  pub fn level_widths(&self) -> Vec<usize> {
+    self.generations().into_values().map(|units| units.len()).collect()
+  }
+
+  /// Units grouped by level, the longest path down to a leaf, leaves at `0`.
+  pub fn generations(&self) -> BTreeMap<usize, Vec<DepId>> {
+    let mut generations = BTreeMap::<_, Vec<_>>::new();
+    for (id, level) in self.levels() {
+      generations.entry(level).or_default().push(id);
+    }
+    generations
+  }
+
+  /// Widest level, `0` for an empty tree.
+  pub fn max_width(&self) -> usize {
+    self.level_widths().into_iter().max().unwrap_or(0)
+  }
+
+  /// Assigns every unit in `exclusive` a slot so that two units holding the
+  /// same exclusive resource get different slots whenever neither depends on
+  /// the other, i.e. whenever they could run at the same time. Greedy, so the
+  /// number of slots is small but not necessarily minimal.
+  pub fn resource_slots(&self, exclusive: &BTreeMap<DepId, BTreeSet<String>>) -> BTreeMap<DepId, usize> {
+    let closures = exclusive
+      .keys()
+      .map(|&id| (id, self.dependencies_of(id).into_iter().collect::<BTreeSet<_>>()))
+      .collect::<BTreeMap<_, _>>();
+    let mut conflicts = exclusive.keys().map(|&id| (id, BTreeSet::new())).collect::<BTreeMap<_, _>>();
+    for (&a, a_tags) in exclusive {
+      for (&b, b_tags) in exclusive.range(a..).skip(1) {
+        let ordered = closures[&a].contains(&b) || closures[&b].contains(&a);
+        if !ordered && !a_tags.is_disjoint(b_tags) {
+          conflicts.get_mut(&a).unwrap().insert(b);
+          conflicts.get_mut(&b).unwrap().insert(a);
+        }
+      }
+    }
+
+    let mut order = conflicts.keys().copied().collect::<Vec<_>>();
+    order.sort_by_key(|id| Reverse(conflicts[id].len()));
+    let mut slots = BTreeMap::new();
+    for id in order {
+      let taken = conflicts[&id].iter().filter_map(|other| slots.get(other)).collect::<BTreeSet<_>>();
+      let slot = (0..).find(|slot| !taken.contains(slot)).unwrap();
+      slots.insert(id, slot);
+    }
+    slots
+  }
+
+  /// Splits the tree into its weakly connected components, ordered by their
+  /// smallest unit. Components share no edges and can be built independently.
+  pub fn components(&self) -> Vec<DepTree> {
+    let dependents = self.reverse_map();
+    let mut seen = BTreeSet::new();
+    let mut components = Vec::new();
+    for &start in dependents.keys() {
+      if seen.contains(&start) {
+        continue;
+      }
+      let mut members = bfs(start, None, |id| {
+        self.direct(id).iter().chain(&dependents[&id]).copied().collect::<Vec<_>>()
+      });
+      members.insert(start, 0);
+      let inner = self
+        .inner
+        .iter()
+        .filter(|(id, _)| members.contains_key(id))
+        .map(|(&id, deps)| (id, deps.clone()))
+        .collect();
+      seen.extend(members.into_keys());
+      components.push(self.derive(inner, self.attrs.keys().copied()));
+    }
+    components
+  }
+
+  /// Units that only ever appear as a dependency and have no entry of their
+  /// own, so they behave as leaves.
+  pub fn undeclared(&self) -> BTreeSet<DepId> {
+    self
+      .inner
+      .values()
+      .flat_map(|deps| deps.iter().copied())
+      .filter(|dep| !self.inner.contains_key(dep))
+      .collect()
+  }
+
+  /// Groups of two or more units with identical dependency sets, direct or
+  /// transitive. Units without dependencies are not grouped.
+  pub fn equivalent_units(&self, transitive: bool) -> Vec<Vec<DepId>> {
+    let mut groups = BTreeMap::<BTreeSet<DepId>, Vec<DepId>>::new();
+    for (&id, deps) in self.inner.iter() {
+      let set = if transitive {
+        self.dependencies_of(id).into_iter().collect::<BTreeSet<_>>()
+      } else {
+        deps.iter().copied().collect()
+      };
+      if !set.is_empty() {
+        groups.entry(set).or_default().push(id);
+      }
+    }
+    groups.into_values().filter(|group| group.len() > 1).collect()
+  }
+
+  /// Direct edges that could be dropped without changing any closure.
+  pub fn redundant_edges(&self) -> Vec<RedundantEdge> {
+    let mut redundant = Vec::new();
+    for (&from, deps) in self.inner.iter() {
+      let targets = deps.iter().copied().collect::<BTreeSet<_>>();
+      for &to in &targets {
+        let mut parent = BTreeMap::new();
+        let mut queue = targets
+          .iter()
+          .filter(|&&dep| dep != to)
+          .map(|&dep| {
+            parent.insert(dep, from);
+            dep
+          })
+          .collect::<VecDeque<_>>();
+        while let Some(id) = queue.pop_front() {
+          if id == to {
+            break;
+          }
+          for &dep in self.direct(id) {
+            if let Entry::Vacant(entry) = parent.entry(dep) {
+              entry.insert(id);
+              queue.push_back(dep);
+            }
+          }
+        }
+        if parent.contains_key(&to) {
+          let mut path = vec![to];
+          while *path.last().unwrap() != from {
+            path.push(parent[path.last().unwrap()]);
+          }
+          path.reverse();
+          redundant.push(RedundantEdge { from, to, path });
+        }
+      }
+    }
+    redundant
+  }
+
+  /// Units reachable from `unit` along at least two edge-disjoint paths, which
+  /// is where conflicting requirements on the same unit come from.
+  pub fn diamonds(&self, unit: DepId) -> Vec<Diamond> {
+    let mut closure = Vec::new();
+    self.post_order(unit, &mut BTreeSet::new(), &mut closure);
+    let mut reverse: BTreeMap<DepId, Vec<DepId>> = BTreeMap::new();
+    for &id in &closure {
+      for &dep in self.direct(id) {
+        reverse.entry(dep).or_default().push(id);
+      }
+    }
+    closure
+      .iter()
+      .rev()
+      .filter(|&&target| target != unit && reverse[&target].len() > 1)
+      .filter_map(|&target| {
+        let mut flow = BTreeSet::new();
+        for _ in 0..2 {
+          if !self.augment(unit, target, &reverse, &mut flow) {
+            return None;
+          }
+        }
+        let first = take_path(unit, target, &mut flow);
+        let second = take_path(unit, target, &mut flow);
+        let diverging = *first[..first.len() - 1]
+          .iter()
+          .rev()
+          .find(|id| second.contains(id))
+          .unwrap();
+        let from = |path: Vec<DepId>| {
+          let start = path.iter().position(|&id| id == diverging).unwrap();
+          path[start..].to_vec()
+        };
+        Some(Diamond {
+          diverging,
+          reconverging: target,
+          paths: [from(first), from(second)],
+        })
+      })
+      .collect()
+  }
+
+  /// Pushes one unit of flow from `source` to `sink` through the residual
+  /// graph of unit-capacity dependency edges.
+  fn augment(
+    &self,
+    source: DepId,
+    sink: DepId,
+    reverse: &BTreeMap<DepId, Vec<DepId>>,
+    flow: &mut BTreeSet<(DepId, DepId)>,
+  ) -> bool {
+    let mut parent: BTreeMap<DepId, (DepId, bool)> = BTreeMap::new();
+    let mut queue = VecDeque::from([source]);
+    while let Some(id) = queue.pop_front() {
+      if id == sink {
+        break;
+      }
+      let forward = self.direct(id).iter().filter(|&&dep| !flow.contains(&(id, dep)));
+      let backward = reverse
+        .get(&id)
+        .into_iter()
+        .flatten()
+        .filter(|&&from| flow.contains(&(from, id)));
+      let steps = forward.map(|&dep| (dep, true)).chain(backward.map(|&from| (from, false)));
+      for (next, is_forward) in steps {
+        if next != source && !parent.contains_key(&next) {
+          parent.insert(next, (id, is_forward));
+          queue.push_back(next);
+        }
+      }
+    }
+    if !parent.contains_key(&sink) {
+      return false;
+    }
+    let mut id = sink;
+    while id != source {
+      let (previous, is_forward) = parent[&id];
+      if is_forward {
+        flow.insert((previous, id));
+      } else {
+        flow.remove(&(id, previous));
+      }
+      id = previous;
+    }
+    true
+  }
+}
+
+impl DepTree {
+  /// For every id present in more than one version, the newest version that
+  /// all dependents of those versions would accept, given
+  /// `accepts(dependent, candidate)`, and that can be shared without creating
+  /// a cycle. With a `Registry` at hand, `accepts` can check that every
+  /// requirement the dependent declares on the candidate's id matches.
+  /// Ids whose dependents already agree on one version are not reported,
+  /// and edges touching pinned units are reported as `held` rather than
+  /// rewritten.
+  pub fn dedup_suggestions(&self, accepts: impl Fn(DepId, DepId) -> bool) -> Vec<DedupSuggestion> {
+    let reverse = self.reverse_map();
+    let mut versions = BTreeMap::<u64, BTreeSet<usize>>::new();
+    for (id, deps) in self.inner.iter() {
+      for &(id, version) in std::iter::once(id).chain(deps.iter()) {
+        versions.entry(id).or_default().insert(version);
+      }
+    }
+
+    let mut suggestions = Vec::new();
+    for (id, versions) in versions.into_iter().filter(|(_, versions)| versions.len() > 1) {
+      let edges = versions
+        .iter()
+        .flat_map(|&version| {
+          let target = (id, version);
+          reverse.get(&target).into_iter().flatten().map(move |&dependent| (dependent, target))
+        })
+        .collect::<BTreeSet<_>>();
+      for &version in versions.iter().rev() {
+        let candidate = (id, version);
+        let (held, rewrites): (Vec<_>, Vec<_>) = edges
+          .iter()
+          .copied()
+          .filter(|&(_, target)| target != candidate)
+          .partition(|&(dependent, target)| self.is_pinned(dependent) || self.is_pinned(target));
+        let shareable = rewrites.iter().all(|&(dependent, _)| {
+          dependent != candidate && accepts(dependent, candidate) && !self.depends_on(candidate, dependent)
+        });
+        if shareable {
+          if !rewrites.is_empty() {
+            let replaced = rewrites
+              .iter()
+              .map(|&(_, (_, version))| version)
+              .filter(|&version| held.iter().all(|&(_, target)| target != (id, version)))
+              .collect::<BTreeSet<_>>();
+            let replaced = replaced.into_iter().collect();
+            suggestions.push(DedupSuggestion { id, version, replaced, rewrites, held });
+          }
+          break;
+        }
+      }
+    }
+    suggestions
+  }
+}
+
+/// Follows and consumes flow edges from `source` until `sink`.
+fn take_path(source: DepId, sink: DepId, flow: &mut BTreeSet<(DepId, DepId)>) -> Vec<DepId> {
+  let mut path = vec![source];
+  let mut id = source;
+  while id != sink {
+    let &edge = flow.range((id, (0, 0))..).find(|edge| edge.0 == id).unwrap();
+    flow.remove(&edge);
+    id = edge.1;
+    path.push(id);
+  }
+  path
+}
@@ -0,0 +1,236 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{DepId, DepTree};
+
+/// How far apart two times within a run of length `makespan` may be and
+/// still count as the same, allowing for rounding.
+fn tolerance(makespan: f64) -> f64 {
+  1e-9 * makespan.max(1.0)
+}
+
+/// Start and finish window of one unit, see `DepTree::critical_path`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UnitTiming {
+  pub earliest_start: f64,
+  pub earliest_finish: f64,
+  pub latest_start: f64,
+  pub latest_finish: f64,
+  /// How far the unit can slip without delaying the whole run.
+  pub slack: f64,
+}
+
+/// Critical path method over a tree with per-unit durations and as many
+/// workers as needed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CriticalPath {
+  /// Time until every unit is finished.
+  pub makespan: f64,
+  pub timings: BTreeMap<DepId, UnitTiming>,
+  /// One longest chain, in the order the units run.
+  pub path: Vec<DepId>,
+}
+
+impl CriticalPath {
+  /// Whether `id` has no slack, allowing for rounding.
+  pub fn is_critical(&self, id: DepId) -> bool {
+    self.timings.get(&id).is_some_and(|timing| timing.slack <= tolerance(self.makespan))
+  }
+
+  /// Every unit without slack.
+  pub fn critical_units(&self) -> BTreeSet<DepId> {
+    self.timings.keys().copied().filter(|&id| self.is_critical(id)).collect()
+  }
+}
+
+/// How far a run has come, see `DepTree::progress`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Progress {
+  /// Percentage of units done.
+  pub by_count: f64,
+  /// Percentage of the total duration done, `by_count` when nothing takes
+  /// any time.
+  pub by_duration: f64,
+  /// Longest chain of remaining work, the time left with enough workers.
+  pub eta: f64,
+}
+
+/// Projected speedup at one worker count, see `DepTree::speedup`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Speedup {
+  pub workers: usize,
+  /// Total duration over the makespan of a simulated run on `workers`.
+  pub simulated: f64,
+  /// Upper bound from the work and the critical path alone,
+  /// `min(workers, 1 / serial_fraction)`.
+  pub bound: f64,
+}
+
+/// How the tree's work parallelizes, see `DepTree::speedup`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeedupAnalysis {
+  /// Critical path duration over the total duration of every unit, `1` when
+  /// nothing takes any time.
+  pub serial_fraction: f64,
+  pub speedups: Vec<Speedup>,
+}
+
+/// Headline figures of a tree, see `DepTree::metrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TreeMetrics {
+  /// Edges on the longest dependency chain.
+  pub height: usize,
+  /// Makespan of `DepTree::critical_path`.
+  pub critical_path: f64,
+  pub max_width: usize,
+  /// Ids present in more than one version among the units the roots reach.
+  pub duplicates: usize,
+}
+
+impl DepTree {
+  /// Height, critical path, widest level and duplicated ids of the tree,
+  /// with the units nothing depends on as the roots.
+  pub fn metrics(&self, durations: &BTreeMap<DepId, f64>) -> TreeMetrics {
+    let roots = self.reverse_map().into_iter().filter(|(_, dependents)| dependents.is_empty()).map(|(id, _)| id);
+    self.metrics_from(&roots.collect::<Vec<_>>(), durations)
+  }
+
+  /// `metrics` of the tree without `edges`, leaving this tree as it is.
+  /// Duplicates are still counted from this tree's roots, so units only the
+  /// removed edges reached no longer count.
+  pub fn what_if_remove(&self, edges: &[(DepId, DepId)], durations: &BTreeMap<DepId, f64>) -> TreeMetrics {
+    let removed = edges.iter().copied().collect::<BTreeSet<_>>();
+    let roots = self.reverse_map().into_iter().filter(|(_, dependents)| dependents.is_empty()).map(|(id, _)| id);
+    let roots = roots.collect::<Vec<_>>();
+    let inner = self
+      .inner
+      .iter()
+      .map(|(&id, deps)| {
+        if deps.iter().any(|&dep| removed.contains(&(id, dep))) {
+          (id, deps.iter().copied().filter(|&dep| !removed.contains(&(id, dep))).collect())
+        } else {
+          (id, deps.clone())
+        }
+      })
+      .collect();
+    self.derive(inner, edges.iter().map(|&(_, dep)| dep)).metrics_from(&roots, durations)
+  }
+
+  /// Edges whose removal alone would shorten `critical_path`, with the time
+  /// saved, largest saving first and ties in edge order. Only edges between
+  /// critical units with no slack between them can help, so only those are
+  /// tried.
+  pub fn edge_sensitivity(&self, durations: &BTreeMap<DepId, f64>) -> Vec<((DepId, DepId), f64)> {
+    let critical = self.critical_path(durations);
+    let mut savings = Vec::new();
+    let mut tried = BTreeSet::new();
+    for (&id, deps) in self.inner.iter().filter(|(&id, _)| critical.is_critical(id)) {
+      let start = critical.timings[&id].earliest_start;
+      for &dep in deps.iter().filter(|&&dep| critical.is_critical(dep)) {
+        let gap = (critical.timings[&dep].earliest_finish - start).abs();
+        if gap > tolerance(critical.makespan) || !tried.insert((id, dep)) {
+          continue;
+        }
+        let mut inner = (*self.inner).clone();
+        inner.insert(id, deps.iter().copied().filter(|&d| d != dep).collect());
+        let saved = critical.makespan - self.derive(inner, [dep]).critical_path(durations).makespan;
+        if saved > 0.0 {
+          savings.push(((id, dep), saved));
+        }
+      }
+    }
+    savings.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    savings
+  }
+
+  fn metrics_from(&self, roots: &[DepId], durations: &BTreeMap<DepId, f64>) -> TreeMetrics {
+    let mut versions = BTreeMap::<u64, usize>::new();
+    for (id, _) in self.install_order(roots) {
+      *versions.entry(id).or_default() += 1;
+    }
+    TreeMetrics {
+      height: self.levels().into_values().max().unwrap_or(0),
+      critical_path: self.critical_path(durations).makespan,
+      max_width: self.max_width(),
+      duplicates: versions.values().filter(|&&count| count > 1).count(),
+    }
+  }
+
+  /// How much running on more workers can help: the share of the work stuck
+  /// on the critical path, and the speedup over one worker at each of the
+  /// `workers` counts. Zero counts are skipped.
+  pub fn speedup(&self, durations: &BTreeMap<DepId, f64>, workers: &[usize]) -> SpeedupAnalysis {
+    let total = self.reverse_map().keys().map(|id| durations.get(id).copied().unwrap_or(0.0)).fold(0.0, |sum, time| sum + time);
+    let critical = self.critical_path(durations).makespan;
+    let serial_fraction = if total > 0.0 { critical / total } else { 1.0 };
+    let speedups = workers
+      .iter()
+      .filter(|&&workers| workers > 0)
+      .map(|&workers| {
+        let makespan = self.simulate(durations, workers).makespan;
+        Speedup {
+          workers,
+          simulated: if makespan > 0.0 { total / makespan } else { 1.0 },
+          bound: (workers as f64).min(1.0 / serial_fraction),
+        }
+      })
+      .collect();
+    SpeedupAnalysis { serial_fraction, speedups }
+  }
+
+  /// Progress once the `done` units are finished. Units without an entry in
+  /// `durations` take no time, and `done` units outside the tree are
+  /// ignored.
+  pub fn progress(&self, done: &BTreeSet<DepId>, durations: &BTreeMap<DepId, f64>) -> Progress {
+    let units = self.reverse_map();
+    let duration = |id: &DepId| durations.get(id).copied().unwrap_or(0.0);
+    let finished = units.keys().filter(|id| done.contains(id)).count();
+    let by_count = if units.is_empty() { 100.0 } else { 100.0 * finished as f64 / units.len() as f64 };
+    let total = units.keys().map(duration).fold(0.0, |sum, time| sum + time);
+    let by_duration = if total > 0.0 {
+      100.0 * units.keys().filter(|id| done.contains(id)).map(duration).fold(0.0, |sum, time| sum + time) / total
+    } else {
+      by_count
+    };
+    let remaining = durations.iter().filter(|(id, _)| !done.contains(id)).map(|(&id, &time)| (id, time)).collect();
+    Progress { by_count, by_duration, eta: self.critical_path(&remaining).makespan }
+  }
+
+  /// Earliest and latest start of every unit when each one runs as soon as
+  /// its dependencies are done, along with its slack and one critical chain.
+  /// Units without an entry in `durations` take no time.
+  pub fn critical_path(&self, durations: &BTreeMap<DepId, f64>) -> CriticalPath {
+    let reverse = self.reverse_map();
+    let duration = |id: DepId| durations.get(&id).copied().unwrap_or(0.0);
+    let mut order = Vec::new();
+    let mut visited = BTreeSet::new();
+    for &id in reverse.keys() {
+      self.post_order(id, &mut visited, &mut order);
+    }
+
+    let mut timings = BTreeMap::<DepId, UnitTiming>::new();
+    for &id in &order {
+      let earliest_start = self.direct(id).iter().map(|dep| timings[dep].earliest_finish).fold(0.0, f64::max);
+      let timing = UnitTiming { earliest_start, earliest_finish: earliest_start + duration(id), ..UnitTiming::default() };
+      timings.insert(id, timing);
+    }
+    let makespan = timings.values().map(|timing| timing.earliest_finish).fold(0.0, f64::max);
+    for &id in order.iter().rev() {
+      let latest_finish = reverse[&id].iter().map(|dependent| timings[dependent].latest_start).fold(makespan, f64::min);
+      let timing = timings.get_mut(&id).unwrap();
+      timing.latest_finish = latest_finish;
+      timing.latest_start = latest_finish - duration(id);
+      timing.slack = timing.latest_start - timing.earliest_start;
+    }
+
+    let mut path = Vec::new();
+    let mut last = timings.iter().filter(|(_, timing)| timing.earliest_finish == makespan).map(|(&id, _)| id).next();
+    while let Some(id) = last {
+      path.push(id);
+      let start = timings[&id].earliest_start;
+      let close = |dep: &DepId| (timings[dep].earliest_finish - start).abs() <= tolerance(makespan);
+      last = self.direct(id).iter().copied().find(close);
+    }
+    path.reverse();
+    CriticalPath { makespan, timings, path }
+  }
+}
@@ -0,0 +1,31 @@
+//! Writers turning a `DepTree`, or a schedule simulated over one, into
+//! formats other tools understand.
+
+mod cytoscape;
+mod d2;
+mod dot;
+mod gantt;
+mod graphml;
+mod html;
+mod jgf;
+mod json;
+mod plantuml;
+mod svg;
+
+pub use cytoscape::{cytoscape, cytoscape_with};
+pub use d2::{d2, d2_grouped};
+pub use dot::{dot, dot_with};
+pub use gantt::{gantt_csv, gantt_mermaid};
+pub use graphml::graphml;
+pub use html::html;
+pub use jgf::jgf;
+pub use json::{json, json_with};
+pub use plantuml::{plantuml, plantuml_grouped};
+pub use svg::svg;
+
+use crate::DepId;
+
+/// Identifier-safe name for formats that can't use the `(id, version)` label.
+fn alias((id, version): DepId) -> String {
+  format!("u{id}_{version}")
+}
@@ -0,0 +1,68 @@
+use std::{
+  collections::BTreeMap,
+  io::{self, Write},
+};
+
+use crate::{json::Json, label, DepId, DepTree};
+
+/// Node `data` fields attributes may not override.
+const RESERVED: [&str; 3] = ["id", "unit", "version"];
+
+/// Writes the tree as Cytoscape.js elements JSON. Node ids are the unit
+/// labels, e.g. `(1, 0)`, and node attributes are added to each node's `data`
+/// except those named after its own `id`, `unit` and `version` fields. Edge
+/// ids are `(1, 0)->(2, 0)`, with `#2`, `#3`, .. appended to repeated edges.
+pub fn cytoscape<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  cytoscape_with(tree, writer, |_| BTreeMap::new())
+}
+
+/// Like `cytoscape`, adding the fields returned by `metadata` to each node's
+/// `data` object, over node attributes of the same name.
+pub fn cytoscape_with<W: Write>(
+  tree: &DepTree,
+  writer: &mut W,
+  metadata: impl Fn(DepId) -> BTreeMap<String, String>,
+) -> io::Result<()> {
+  let nodes = tree
+    .reverse_map()
+    .into_keys()
+    .map(|unit| {
+      let mut extra = tree
+        .attrs(unit)
+        .map(|(key, value)| (key.to_owned(), Json::from(value)))
+        .collect::<BTreeMap<_, _>>();
+      extra.extend(metadata(unit).into_iter().map(|(key, value)| (key, Json::from(value))));
+      let mut data = vec![
+        ("id".to_owned(), Json::from(label(unit))),
+        ("unit".to_owned(), Json::from(unit.0)),
+        ("version".to_owned(), Json::from(unit.1)),
+      ];
+      data.extend(extra.into_iter().filter(|(key, _)| !RESERVED.contains(&key.as_str())));
+      Json::object([("data", Json::Object(data))])
+    })
+    .collect();
+  let mut seen = BTreeMap::<(DepId, DepId), usize>::new();
+  let edges = tree
+    .inner
+    .iter()
+    .flat_map(|(&unit, deps)| deps.iter().map(move |&dep| (unit, dep)))
+    .map(|(unit, dep)| {
+      let occurrence = seen.entry((unit, dep)).or_default();
+      *occurrence += 1;
+      let mut id = format!("{}->{}", label(unit), label(dep));
+      if *occurrence > 1 {
+        id.push_str(&format!("#{occurrence}"));
+      }
+      Json::object([(
+        "data",
+        Json::object([
+          ("id", Json::from(id)),
+          ("source", Json::from(label(unit))),
+          ("target", Json::from(label(dep))),
+        ]),
+      )])
+    })
+    .collect();
+  let elements = Json::object([("nodes", Json::Array(nodes)), ("edges", Json::Array(edges))]);
+  writeln!(writer, "{elements}")
+}
@@ -0,0 +1,57 @@
+use std::{
+  collections::BTreeMap,
+  io::{self, Write},
+};
+
+use super::alias;
+use crate::{label, DepId, DepTree};
+
+/// Writes the tree as a D2 diagram.
+pub fn d2<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  d2_grouped(tree, writer, |_| None)
+}
+
+/// Like `d2`, nesting units in a container per name returned by `group`.
+/// Edges reference units by their full container path.
+pub fn d2_grouped<W: Write>(
+  tree: &DepTree,
+  writer: &mut W,
+  group: impl Fn(DepId) -> Option<String>,
+) -> io::Result<()> {
+  let mut groups: BTreeMap<Option<String>, Vec<DepId>> = BTreeMap::new();
+  let mut paths = BTreeMap::new();
+  for unit in tree.reverse_map().into_keys() {
+    let name = group(unit);
+    let path = match &name {
+      Some(name) => format!("{}.{}", quote(name), alias(unit)),
+      None => alias(unit),
+    };
+    paths.insert(unit, path);
+    groups.entry(name).or_default().push(unit);
+  }
+  for (name, units) in groups.iter() {
+    let indent = match name {
+      Some(name) => {
+        writeln!(writer, "{}: {{", quote(name))?;
+        "  "
+      }
+      None => "",
+    };
+    for &unit in units {
+      writeln!(writer, "{indent}{}: {}", alias(unit), quote(&label(unit)))?;
+    }
+    if name.is_some() {
+      writeln!(writer, "}}")?;
+    }
+  }
+  for (unit, deps) in tree.inner.iter() {
+    for dep in deps.iter() {
+      writeln!(writer, "{} -> {}", paths[unit], paths[dep])?;
+    }
+  }
+  Ok(())
+}
+
+fn quote(text: &str) -> String {
+  format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,65 @@
+use std::{
+  collections::BTreeMap,
+  io::{self, Write},
+};
+
+use super::alias;
+use crate::{label, DepId, DepTree};
+
+/// Writes the tree as a Graphviz DOT digraph labelled with unit labels, with
+/// node attributes as DOT attributes on each node.
+pub fn dot<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  dot_with(tree, writer, |_| BTreeMap::new(), |_, _| BTreeMap::new())
+}
+
+/// Like `dot`, adding the DOT attributes returned by `node` and `edge`, e.g.
+/// `tooltip`, `color` or `style`. Those returned by `node` win over node
+/// attributes of the same name, and a `label` from either replaces the unit
+/// label.
+pub fn dot_with<W: Write>(
+  tree: &DepTree,
+  writer: &mut W,
+  node: impl Fn(DepId) -> BTreeMap<String, String>,
+  edge: impl Fn(DepId, DepId) -> BTreeMap<String, String>,
+) -> io::Result<()> {
+  writeln!(writer, "digraph {{")?;
+  for unit in tree.reverse_map().into_keys() {
+    let mut attributes = tree
+      .attrs(unit)
+      .map(|(key, value)| (key.to_owned(), value.to_string()))
+      .collect::<BTreeMap<_, _>>();
+    attributes.extend(node(unit));
+    attributes.entry("label".to_owned()).or_insert_with(|| label(unit));
+    writeln!(writer, "  {}{};", alias(unit), attribute_list(&attributes))?;
+  }
+  for (&unit, deps) in tree.inner.iter() {
+    for &dep in deps.iter() {
+      writeln!(writer, "  {} -> {}{};", alias(unit), alias(dep), attribute_list(&edge(unit, dep)))?;
+    }
+  }
+  writeln!(writer, "}}")
+}
+
+fn attribute_list(attributes: &BTreeMap<String, String>) -> String {
+  if attributes.is_empty() {
+    return String::new();
+  }
+  let attributes = attributes
+    .iter()
+    .map(|(key, value)| {
+      if key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        format!("{key}={}", quote(value))
+      } else {
+        format!("{}={}", quote(key), quote(value))
+      }
+    })
+    .collect::<Vec<_>>();
+  format!(" [{}]", attributes.join(", "))
+}
+
+fn quote(text: &str) -> String {
+  format!(
+    "\"{}\"",
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+  )
+}
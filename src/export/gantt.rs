@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+
+use super::alias;
+use crate::{label, Simulation};
+
+/// Writes the schedule of a simulation as CSV with a `unit,worker,start,end`
+/// header, one row per unit in start order.
+pub fn gantt_csv<W: Write>(simulation: &Simulation, writer: &mut W) -> io::Result<()> {
+  writeln!(writer, "unit,worker,start,end")?;
+  for scheduled in &simulation.schedule {
+    writeln!(writer, "\"{}\",{},{},{}", label(scheduled.unit), scheduled.worker, scheduled.start, scheduled.end)?;
+  }
+  Ok(())
+}
+
+/// Writes the schedule of a simulation as a Mermaid gantt chart with a
+/// section per worker. Times are read as seconds and written as milliseconds
+/// since the start, Mermaid's `x` date format.
+pub fn gantt_mermaid<W: Write>(simulation: &Simulation, writer: &mut W) -> io::Result<()> {
+  writeln!(writer, "gantt")?;
+  writeln!(writer, "  dateFormat x")?;
+  writeln!(writer, "  axisFormat %M:%S")?;
+  let workers = simulation.schedule.iter().map(|scheduled| scheduled.worker + 1).max().unwrap_or(0);
+  for worker in 0..workers {
+    writeln!(writer, "  section worker {worker}")?;
+    for scheduled in simulation.schedule.iter().filter(|scheduled| scheduled.worker == worker) {
+      let millis = |seconds: f64| (seconds * 1000.0).round() as u64;
+      writeln!(
+        writer,
+        "  {} :{}, {}, {}",
+        label(scheduled.unit),
+        alias(scheduled.unit),
+        millis(scheduled.start),
+        millis(scheduled.end)
+      )?;
+    }
+  }
+  Ok(())
+}
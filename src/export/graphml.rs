@@ -0,0 +1,61 @@
+use std::{
+  collections::BTreeMap,
+  io::{self, Write},
+};
+
+use crate::{label, xml::escape, AttrValue, DepTree};
+
+/// Writes the tree as GraphML. Node ids are unit labels, and every node also
+/// carries its `unit` and `version` as data so tools free to rename ids keep
+/// them intact. Node attributes become `attr.<key>` data, typed after the
+/// first value seen for each key. Attributes named `unit` or `version` are
+/// left out.
+pub fn graphml<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  let units = tree.reverse_map().into_keys().collect::<Vec<_>>();
+  let attrs = |unit| tree.attrs(unit).filter(|&(key, _)| key != "unit" && key != "version");
+  let mut keys = BTreeMap::new();
+  for &unit in &units {
+    for (key, value) in attrs(unit) {
+      keys.entry(key).or_insert(match value {
+        AttrValue::String(_) => "string",
+        AttrValue::Int(_) => "long",
+        AttrValue::Bool(_) => "boolean",
+      });
+    }
+  }
+
+  writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+  writeln!(writer, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+  writeln!(writer, r#"  <key id="unit" for="node" attr.name="unit" attr.type="long"/>"#)?;
+  writeln!(writer, r#"  <key id="version" for="node" attr.name="version" attr.type="long"/>"#)?;
+  for (key, kind) in &keys {
+    let key = escape(key);
+    writeln!(writer, r#"  <key id="attr.{key}" for="node" attr.name="{key}" attr.type="{kind}"/>"#)?;
+  }
+  writeln!(writer, r#"  <graph id="G" edgedefault="directed">"#)?;
+  for unit in units {
+    write!(
+      writer,
+      r#"    <node id="{}"><data key="unit">{}</data><data key="version">{}</data>"#,
+      escape(&label(unit)),
+      unit.0,
+      unit.1,
+    )?;
+    for (key, value) in attrs(unit) {
+      write!(writer, r#"<data key="attr.{}">{}</data>"#, escape(key), escape(&value.to_string()))?;
+    }
+    writeln!(writer, "</node>")?;
+  }
+  for (&unit, deps) in tree.inner.iter() {
+    for &dep in deps.iter() {
+      writeln!(
+        writer,
+        r#"    <edge source="{}" target="{}"/>"#,
+        escape(&label(unit)),
+        escape(&label(dep)),
+      )?;
+    }
+  }
+  writeln!(writer, "  </graph>")?;
+  writeln!(writer, "</graphml>")
+}
@@ -0,0 +1,160 @@
+use std::io::{self, Write};
+
+use super::svg::{layout, Layout, NODE_HEIGHT, NODE_WIDTH};
+use crate::{label, DepTree};
+
+const VIEWER: &str = r#"const svg = document.getElementById("graph");
+const ns = "http://www.w3.org/2000/svg";
+const view = { x: 0, y: 0, w: graph.width, h: graph.height };
+const nodes = new Map();
+const byId = new Map(graph.nodes.map((node) => [node.id, node]));
+const dependents = new Map(graph.nodes.map((node) => [node.id, []]));
+for (const [from, to] of graph.edges) dependents.get(to).push(from);
+
+function applyView() {
+  svg.setAttribute("viewBox", `${view.x} ${view.y} ${view.w} ${view.h}`);
+}
+
+for (const [from, to] of graph.edges) {
+  const a = byId.get(from);
+  const b = byId.get(to);
+  const line = document.createElementNS(ns, "line");
+  line.setAttribute("x1", a.x + graph.nodeWidth / 2);
+  line.setAttribute("y1", a.y + graph.nodeHeight);
+  line.setAttribute("x2", b.x + graph.nodeWidth / 2);
+  line.setAttribute("y2", b.y);
+  line.setAttribute("class", "edge");
+  line.dataset.from = from;
+  line.dataset.to = to;
+  svg.appendChild(line);
+}
+for (const node of graph.nodes) {
+  const group = document.createElementNS(ns, "g");
+  group.setAttribute("class", "node");
+  group.setAttribute("transform", `translate(${node.x} ${node.y})`);
+  const rect = document.createElementNS(ns, "rect");
+  rect.setAttribute("width", graph.nodeWidth);
+  rect.setAttribute("height", graph.nodeHeight);
+  rect.setAttribute("rx", 4);
+  const text = document.createElementNS(ns, "text");
+  text.setAttribute("x", graph.nodeWidth / 2);
+  text.setAttribute("y", graph.nodeHeight / 2);
+  text.textContent = node.id;
+  group.append(rect, text);
+  group.addEventListener("click", (event) => {
+    event.stopPropagation();
+    highlight(node.id);
+  });
+  svg.appendChild(group);
+  nodes.set(node.id, group);
+}
+
+function highlight(id) {
+  const reached = new Set([id]);
+  const stack = [id];
+  while (stack.length) {
+    for (const dependent of dependents.get(stack.pop())) {
+      if (!reached.has(dependent)) {
+        reached.add(dependent);
+        stack.push(dependent);
+      }
+    }
+  }
+  for (const [nodeId, group] of nodes) {
+    group.classList.toggle("selected", nodeId === id);
+    group.classList.toggle("dependent", nodeId !== id && reached.has(nodeId));
+    group.classList.toggle("faded", !reached.has(nodeId));
+  }
+  for (const line of svg.querySelectorAll(".edge")) {
+    line.classList.toggle("faded", !(reached.has(line.dataset.from) && reached.has(line.dataset.to)));
+  }
+}
+
+function clear() {
+  for (const element of svg.querySelectorAll(".selected, .dependent, .faded, .match")) {
+    element.classList.remove("selected", "dependent", "faded", "match");
+  }
+}
+
+svg.addEventListener("click", clear);
+document.getElementById("search").addEventListener("input", (event) => {
+  const query = event.target.value.trim();
+  for (const [id, group] of nodes) {
+    group.classList.toggle("match", query !== "" && id.includes(query));
+  }
+});
+
+let drag = null;
+svg.addEventListener("mousedown", (event) => {
+  drag = { x: event.clientX, y: event.clientY };
+});
+window.addEventListener("mouseup", () => {
+  drag = null;
+});
+window.addEventListener("mousemove", (event) => {
+  if (!drag) return;
+  const scale = view.w / svg.clientWidth;
+  view.x -= (event.clientX - drag.x) * scale;
+  view.y -= (event.clientY - drag.y) * scale;
+  drag = { x: event.clientX, y: event.clientY };
+  applyView();
+});
+svg.addEventListener("wheel", (event) => {
+  event.preventDefault();
+  const factor = event.deltaY > 0 ? 1.1 : 1 / 1.1;
+  const box = svg.getBoundingClientRect();
+  const px = view.x + ((event.clientX - box.left) / box.width) * view.w;
+  const py = view.y + ((event.clientY - box.top) / box.height) * view.h;
+  view.x = px - (px - view.x) * factor;
+  view.y = py - (py - view.y) * factor;
+  view.w *= factor;
+  view.h *= factor;
+  applyView();
+});
+applyView();
+"#;
+
+const STYLE: &str = r#"body { margin: 0; font-family: sans-serif; }
+header { padding: 8px; border-bottom: 1px solid #ccc; }
+#graph { width: 100vw; height: calc(100vh - 45px); cursor: grab; }
+.node rect { fill: #f4f4f4; stroke: #333; }
+.node text { font: 12px monospace; text-anchor: middle; dominant-baseline: middle; pointer-events: none; }
+.node { cursor: pointer; }
+.edge { stroke: #555; }
+.match rect { fill: #ffe58a; }
+.selected rect { fill: #8ac6ff; }
+.dependent rect { fill: #cde6ff; }
+.faded { opacity: 0.2; }
+"#;
+
+/// Writes a single HTML page embedding the laid out tree as JSON together with
+/// a small viewer: drag to pan, scroll to zoom, type to search and click a
+/// unit to highlight everything that depends on it.
+pub fn html<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  let Layout { width, height, positions } = layout(tree);
+  writeln!(writer, "<!DOCTYPE html>")?;
+  writeln!(writer, r#"<html><head><meta charset="utf-8"><title>Dependency tree</title>"#)?;
+  writeln!(writer, "<style>\n{STYLE}</style></head><body>")?;
+  writeln!(writer, r#"<header><input id="search" placeholder="Search units"></header>"#)?;
+  writeln!(writer, r#"<svg id="graph" xmlns="http://www.w3.org/2000/svg"></svg>"#)?;
+  write!(
+    writer,
+    r#"<script>const graph = {{"width": {width}, "height": {height}, "nodeWidth": {NODE_WIDTH}, "nodeHeight": {NODE_HEIGHT}, "nodes": ["#
+  )?;
+  for (i, (&unit, &(x, y))) in positions.iter().enumerate() {
+    let separator = if i == 0 { "" } else { ", " };
+    write!(writer, r#"{separator}{{"id": "{}", "x": {x}, "y": {y}}}"#, label(unit))?;
+  }
+  write!(writer, r#"], "edges": ["#)?;
+  let edges = tree
+    .inner
+    .iter()
+    .flat_map(|(&unit, deps)| deps.iter().map(move |&dep| (unit, dep)));
+  for (i, (unit, dep)) in edges.enumerate() {
+    let separator = if i == 0 { "" } else { ", " };
+    write!(writer, r#"{separator}["{}", "{}"]"#, label(unit), label(dep))?;
+  }
+  writeln!(writer, "]}};")?;
+  writeln!(writer, "{VIEWER}</script>")?;
+  writeln!(writer, "</body></html>")
+}
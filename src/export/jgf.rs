@@ -0,0 +1,48 @@
+use std::io::{self, Write};
+
+use crate::{json::Json, label, DepTree};
+
+/// Writes the tree as a JSON Graph Format (v2) document. Node ids are unit
+/// labels and every node carries its `unit`, `version` and attributes as
+/// metadata, leaving out attributes named `unit` or `version`.
+pub fn jgf<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  let nodes = tree
+    .reverse_map()
+    .into_keys()
+    .map(|unit| {
+      let mut metadata = vec![
+        ("unit".to_owned(), Json::from(unit.0)),
+        ("version".to_owned(), Json::from(unit.1)),
+      ];
+      metadata.extend(
+        tree
+          .attrs(unit)
+          .filter(|&(key, _)| key != "unit" && key != "version")
+          .map(|(key, value)| (key.to_owned(), Json::from(value))),
+      );
+      let node = Json::object([("label", Json::from(label(unit))), ("metadata", Json::Object(metadata))]);
+      (label(unit), node)
+    })
+    .collect();
+  let edges = tree
+    .inner
+    .iter()
+    .flat_map(|(&unit, deps)| deps.iter().map(move |&dep| (unit, dep)))
+    .map(|(unit, dep)| {
+      Json::object([
+        ("source", Json::from(label(unit))),
+        ("target", Json::from(label(dep))),
+        ("relation", Json::from("depends on")),
+      ])
+    })
+    .collect();
+  let document = Json::object([(
+    "graph",
+    Json::object([
+      ("directed", Json::Bool(true)),
+      ("nodes", Json::Object(nodes)),
+      ("edges", Json::Array(edges)),
+    ]),
+  )]);
+  writeln!(writer, "{document}")
+}
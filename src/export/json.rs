@@ -0,0 +1,59 @@
+use std::{
+  collections::BTreeMap,
+  io::{self, Write},
+};
+
+use crate::{json::Json, DepId, DepTree};
+
+/// Writes the tree as `{"units": [...]}`, one entry per unit with its `id`,
+/// `version`, direct `deps` and, when it has any, node attributes under
+/// `metadata`. Undeclared units are included.
+pub fn json<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  json_with(tree, writer, |_| BTreeMap::new(), |_, _| BTreeMap::new())
+}
+
+/// Like `json`, adding the fields returned by `node` to each unit's
+/// `metadata`, over node attributes of the same name, and those returned by
+/// `edge` to a `metadata` object on each dependency.
+pub fn json_with<W: Write>(
+  tree: &DepTree,
+  writer: &mut W,
+  node: impl Fn(DepId) -> BTreeMap<String, String>,
+  edge: impl Fn(DepId, DepId) -> BTreeMap<String, String>,
+) -> io::Result<()> {
+  let units = tree
+    .reverse_map()
+    .into_keys()
+    .map(|unit| {
+      let deps = tree
+        .direct(unit)
+        .iter()
+        .map(|&dep| Json::object(with_metadata(fields(dep), strings(edge(unit, dep)))))
+        .collect();
+      let mut fields = fields(unit);
+      fields.push(("deps", Json::Array(deps)));
+      let mut metadata = tree
+        .attrs(unit)
+        .map(|(key, value)| (key.to_owned(), Json::from(value)))
+        .collect::<BTreeMap<_, _>>();
+      metadata.extend(strings(node(unit)));
+      Json::object(with_metadata(fields, metadata.into_iter().collect()))
+    })
+    .collect();
+  writeln!(writer, "{}", Json::object([("units", Json::Array(units))]))
+}
+
+fn fields((id, version): DepId) -> Vec<(&'static str, Json)> {
+  vec![("id", Json::from(id)), ("version", Json::from(version))]
+}
+
+fn strings(metadata: BTreeMap<String, String>) -> Vec<(String, Json)> {
+  metadata.into_iter().map(|(key, value)| (key, Json::from(value))).collect()
+}
+
+fn with_metadata(mut fields: Vec<(&'static str, Json)>, metadata: Vec<(String, Json)>) -> Vec<(&'static str, Json)> {
+  if !metadata.is_empty() {
+    fields.push(("metadata", Json::Object(metadata)));
+  }
+  fields
+}
@@ -0,0 +1,46 @@
+use std::{
+  collections::BTreeMap,
+  io::{self, Write},
+};
+
+use super::alias;
+use crate::{label, DepId, DepTree};
+
+/// Writes the tree as a PlantUML component diagram.
+pub fn plantuml<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  plantuml_grouped(tree, writer, |_| None)
+}
+
+/// Like `plantuml`, wrapping units in a `package` per name returned by `group`.
+pub fn plantuml_grouped<W: Write>(
+  tree: &DepTree,
+  writer: &mut W,
+  group: impl Fn(DepId) -> Option<String>,
+) -> io::Result<()> {
+  let mut groups: BTreeMap<Option<String>, Vec<DepId>> = BTreeMap::new();
+  for unit in tree.reverse_map().into_keys() {
+    groups.entry(group(unit)).or_default().push(unit);
+  }
+  writeln!(writer, "@startuml")?;
+  for (name, units) in groups.iter() {
+    let indent = match name {
+      Some(name) => {
+        writeln!(writer, "package \"{}\" {{", name.replace('"', "'"))?;
+        "  "
+      }
+      None => "",
+    };
+    for &unit in units {
+      writeln!(writer, "{indent}[{}] as {}", label(unit), alias(unit))?;
+    }
+    if name.is_some() {
+      writeln!(writer, "}}")?;
+    }
+  }
+  for (&unit, deps) in tree.inner.iter() {
+    for &dep in deps.iter() {
+      writeln!(writer, "{} --> {}", alias(unit), alias(dep))?;
+    }
+  }
+  writeln!(writer, "@enduml")
+}
@@ -0,0 +1,125 @@
+use std::{
+  collections::BTreeMap,
+  io::{self, Write},
+};
+
+use crate::{label, DepId, DepTree};
+
+pub(super) const NODE_WIDTH: f64 = 100.0;
+pub(super) const NODE_HEIGHT: f64 = 30.0;
+const H_GAP: f64 = 20.0;
+const V_GAP: f64 = 60.0;
+const SWEEPS: usize = 4;
+
+/// Writes a standalone SVG with dependents drawn above their dependencies.
+/// Rows are the tree's levels, and units within a row are ordered by the
+/// barycenter of their neighbours to keep edge crossings down.
+pub fn svg<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  let Layout { width, height, positions } = layout(tree);
+  writeln!(
+    writer,
+    r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+  )?;
+  writeln!(
+    writer,
+    r##"  <defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="#555"/></marker></defs>"##
+  )?;
+  writeln!(writer, r#"  <g class="edges">"#)?;
+  for (unit, deps) in tree.inner.iter() {
+    let (x1, y1) = positions[unit];
+    for dep in deps.iter() {
+      let (x2, y2) = positions[dep];
+      writeln!(
+        writer,
+        r##"    <line x1="{}" y1="{}" x2="{}" y2="{y2}" stroke="#555" marker-end="url(#arrow)"/>"##,
+        x1 + NODE_WIDTH / 2.0,
+        y1 + NODE_HEIGHT,
+        x2 + NODE_WIDTH / 2.0,
+      )?;
+    }
+  }
+  writeln!(writer, "  </g>")?;
+  writeln!(writer, r#"  <g class="nodes">"#)?;
+  for (&unit, &(x, y)) in positions.iter() {
+    writeln!(
+      writer,
+      r##"    <g class="node"><rect x="{x}" y="{y}" width="{NODE_WIDTH}" height="{NODE_HEIGHT}" rx="4" fill="#f4f4f4" stroke="#333"/><text x="{}" y="{}" text-anchor="middle" dominant-baseline="middle" font-family="monospace" font-size="12">{}</text></g>"##,
+      x + NODE_WIDTH / 2.0,
+      y + NODE_HEIGHT / 2.0,
+      label(unit),
+    )?;
+  }
+  writeln!(writer, "  </g>")?;
+  writeln!(writer, "</svg>")
+}
+
+pub(super) struct Layout {
+  pub width: f64,
+  pub height: f64,
+  /// Top-left corner of every node box.
+  pub positions: BTreeMap<DepId, (f64, f64)>,
+}
+
+pub(super) fn layout(tree: &DepTree) -> Layout {
+  let layers = layers(tree);
+  let widest = layers.iter().map(Vec::len).max().unwrap_or(0) as f64;
+  let width = widest * (NODE_WIDTH + H_GAP) + H_GAP;
+  let height = layers.len() as f64 * (NODE_HEIGHT + V_GAP) - V_GAP + 2.0 * H_GAP;
+  let height = height.max(2.0 * H_GAP);
+  let mut positions = BTreeMap::new();
+  for (row, layer) in layers.iter().enumerate() {
+    let offset = (width - layer.len() as f64 * (NODE_WIDTH + H_GAP) + H_GAP) / 2.0;
+    for (column, &unit) in layer.iter().enumerate() {
+      let x = offset + column as f64 * (NODE_WIDTH + H_GAP);
+      let y = H_GAP + row as f64 * (NODE_HEIGHT + V_GAP);
+      positions.insert(unit, (x, y));
+    }
+  }
+  Layout { width, height, positions }
+}
+
+/// Rows from the roots down, each ordered by repeated barycenter sweeps.
+fn layers(tree: &DepTree) -> Vec<Vec<DepId>> {
+  let levels = tree.levels();
+  let height = levels.values().max().map_or(0, |max| max + 1);
+  let mut layers = vec![Vec::new(); height];
+  for (&unit, &level) in levels.iter() {
+    layers[height - 1 - level].push(unit);
+  }
+  let dependents = tree.reverse_map();
+  for sweep in 0..SWEEPS {
+    let rows: Vec<usize> = if sweep % 2 == 0 {
+      (1..height).collect()
+    } else {
+      (0..height.saturating_sub(1)).rev().collect()
+    };
+    for row in rows {
+      let index = layers
+        .iter()
+        .flat_map(|layer| layer.iter().enumerate().map(|(i, &unit)| (unit, i as f64)))
+        .collect::<BTreeMap<_, _>>();
+      let neighbours = |unit: &DepId| -> Vec<DepId> {
+        if sweep % 2 == 0 {
+          dependents[unit].clone()
+        } else {
+          tree.inner.get(unit).map(|deps| deps.to_vec()).unwrap_or_default()
+        }
+      };
+      let mut keyed = layers[row]
+        .iter()
+        .map(|unit| {
+          let neighbours = neighbours(unit);
+          let barycenter = if neighbours.is_empty() {
+            index[unit]
+          } else {
+            neighbours.iter().map(|n| index[n]).sum::<f64>() / neighbours.len() as f64
+          };
+          (barycenter, *unit)
+        })
+        .collect::<Vec<_>>();
+      keyed.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+      layers[row] = keyed.into_iter().map(|(_, unit)| unit).collect();
+    }
+  }
+  layers
+}
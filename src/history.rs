@@ -0,0 +1,67 @@
+use std::time::SystemTime;
+
+use crate::{ChangeEvent, DepTree, DepTreeBuilderResult};
+
+#[derive(Clone, Debug)]
+pub struct Revision {
+  pub label: String,
+  pub timestamp: SystemTime,
+  pub changes: Vec<ChangeEvent>,
+}
+
+/// Sequence of labelled deltas on top of a base tree. Revision `0` is the base,
+/// revision `n` is the tree after the first `n` deltas.
+#[derive(Clone, Debug)]
+pub struct History {
+  base: DepTree,
+  head: DepTree,
+  revisions: Vec<Revision>,
+}
+
+impl History {
+  pub fn new(base: DepTree) -> Self {
+    Self {
+      head: base.clone(),
+      base,
+      revisions: Vec::new(),
+    }
+  }
+
+  /// Records `changes` as a new revision and returns its number. Nothing is
+  /// recorded if the changes leave the tree cyclic.
+  pub fn apply(
+    &mut self,
+    label: impl Into<String>,
+    changes: Vec<ChangeEvent>,
+  ) -> DepTreeBuilderResult<usize> {
+    self.head = self.head.apply(&changes)?;
+    self.revisions.push(Revision {
+      label: label.into(),
+      timestamp: SystemTime::now(),
+      changes,
+    });
+    Ok(self.revisions.len())
+  }
+
+  pub fn head(&self) -> &DepTree {
+    &self.head
+  }
+
+  pub fn revisions(&self) -> &[Revision] {
+    &self.revisions
+  }
+
+  pub fn checkout(&self, revision: usize) -> Option<DepTree> {
+    if revision > self.revisions.len() {
+      return None;
+    }
+    if revision == self.revisions.len() {
+      return Some(self.head.clone());
+    }
+    let mut tree = self.base.clone();
+    for applied in &self.revisions[..revision] {
+      tree = tree.apply(&applied.changes).expect("recorded revisions are acyclic");
+    }
+    Some(tree)
+  }
+}
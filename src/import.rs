@@ -0,0 +1,107 @@
+//! Readers producing a `DepTreeBuilder` from formats other tools write.
+
+mod cargo;
+mod elf;
+mod graphml;
+mod jgf;
+mod jvm;
+mod manifest;
+mod python;
+
+pub use cargo::{cargo_metadata, DepKind};
+pub use elf::{elf_needed, ldd};
+pub use graphml::graphml;
+pub use jgf::jgf;
+pub use jvm::{gradle_dependencies, maven_tree};
+pub use manifest::manifest_toml;
+pub use python::{poetry_lock, requirements_txt};
+
+use std::{collections::BTreeMap, io};
+
+use thiserror::Error;
+
+use crate::DepId;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+  #[error("failed to read input: {0}")]
+  Io(#[from] io::Error),
+  #[error("syntax error at {position}: {message}")]
+  Syntax { position: usize, message: String },
+  #[error("line {line}: {message}")]
+  Line { line: usize, message: String },
+  #[error("invalid document: {0}")]
+  Invalid(String),
+  #[error("node `{0}` has no unit id")]
+  UnknownNode(String),
+}
+
+pub type ImportResult<T> = Result<T, ImportError>;
+
+fn read_to_string(mut reader: impl io::Read) -> ImportResult<String> {
+  let mut text = String::new();
+  reader.read_to_string(&mut text)?;
+  Ok(text)
+}
+
+/// Package names and versions behind the units a package manager importer
+/// produced. Every name gets its own unit id, numbered in order of first
+/// appearance, and every version of it its own version index.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Packages {
+  ids: BTreeMap<String, u64>,
+  packages: Vec<(String, Vec<String>)>,
+}
+
+impl Packages {
+  pub fn id(&self, name: &str, version: &str) -> Option<DepId> {
+    let id = *self.ids.get(name)?;
+    let version = self.packages[id as usize].1.iter().position(|known| known == version)?;
+    Some((id, version))
+  }
+
+  /// Every version of `name`, in order of first appearance.
+  pub fn ids_of(&self, name: &str) -> Vec<DepId> {
+    self.ids.get(name).map_or_else(Vec::new, |&id| {
+      (0..self.packages[id as usize].1.len()).map(|version| (id, version)).collect()
+    })
+  }
+
+  pub fn name(&self, (id, _): DepId) -> Option<&str> {
+    self.packages.get(id as usize).map(|(name, _)| name.as_str())
+  }
+
+  pub fn version(&self, (id, version): DepId) -> Option<&str> {
+    self.packages.get(id as usize)?.1.get(version).map(String::as_str)
+  }
+
+  /// Number of distinct package versions.
+  pub fn len(&self) -> usize {
+    self.packages.iter().map(|(_, versions)| versions.len()).sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.packages.is_empty()
+  }
+
+  /// Every unit with its package name and version.
+  pub fn iter(&self) -> impl Iterator<Item = (DepId, &str, &str)> {
+    self.packages.iter().enumerate().flat_map(|(id, (name, versions))| {
+      versions.iter().enumerate().map(move |(version, text)| ((id as u64, version), name.as_str(), text.as_str()))
+    })
+  }
+
+  /// Unit of `name` at `version`, assigning new ids as needed.
+  fn insert(&mut self, name: &str, version: &str) -> DepId {
+    let id = *self.ids.entry(name.to_owned()).or_insert_with(|| {
+      self.packages.push((name.to_owned(), Vec::new()));
+      self.packages.len() as u64 - 1
+    });
+    let versions = &mut self.packages[id as usize].1;
+    let index = versions.iter().position(|known| known == version).unwrap_or_else(|| {
+      versions.push(version.to_owned());
+      versions.len() - 1
+    });
+    (id, index)
+  }
+}
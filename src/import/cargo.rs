@@ -0,0 +1,101 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  io::Read,
+};
+
+use super::{read_to_string, ImportError, ImportResult, Packages};
+use crate::{json::Json, DepId, DepTreeBuilder};
+
+/// Section of a Cargo manifest a dependency is declared in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DepKind {
+  Normal,
+  Dev,
+  Build,
+}
+
+impl DepKind {
+  fn parse(kind: &Json) -> ImportResult<Self> {
+    match kind {
+      Json::Null => Ok(Self::Normal),
+      Json::String(kind) if kind == "normal" => Ok(Self::Normal),
+      Json::String(kind) if kind == "dev" => Ok(Self::Dev),
+      Json::String(kind) if kind == "build" => Ok(Self::Build),
+      _ => Err(ImportError::Invalid(format!("unknown dependency kind `{kind}`"))),
+    }
+  }
+}
+
+fn field<'a>(value: &'a Json, key: &str, what: &str) -> ImportResult<&'a Json> {
+  value.get(key).ok_or_else(|| ImportError::Invalid(format!("{what} without `{key}`")))
+}
+
+fn string<'a>(value: &'a Json, key: &str, what: &str) -> ImportResult<&'a str> {
+  field(value, key, what)?.as_str().ok_or_else(|| ImportError::Invalid(format!("{what} `{key}` must be a string")))
+}
+
+/// Reads the JSON printed by `cargo metadata --format-version 1`. Every
+/// package becomes a unit named after its crate, and every edge of the
+/// resolved graph carries the `BTreeSet<DepKind>` it is declared under as
+/// its payload, see `DepTree::edge`. Workspace members get a `workspace`
+/// attribute set to `true`.
+///
+/// With `--no-deps` there is no resolved graph, so dependencies are matched
+/// by name against the packages listed instead.
+pub fn cargo_metadata(reader: impl Read) -> ImportResult<(DepTreeBuilder, Packages)> {
+  let text = read_to_string(reader)?;
+  let document = Json::parse(&text).map_err(|(position, message)| ImportError::Syntax { position, message })?;
+  let mut builder = DepTreeBuilder::new();
+  let mut packages = Packages::default();
+  let mut units = BTreeMap::new();
+  let listed = field(&document, "packages", "metadata")?.as_array().unwrap_or_default();
+  for package in listed {
+    let unit = packages.insert(string(package, "name", "package")?, string(package, "version", "package")?);
+    units.insert(string(package, "id", "package")?, unit);
+    builder.with_dep(unit, Vec::new());
+  }
+  let unit_of = |id: &str| units.get(id).copied().ok_or_else(|| ImportError::UnknownNode(id.to_owned()));
+  for member in field(&document, "workspace_members", "metadata")?.as_array().unwrap_or_default() {
+    let id = member.as_str().ok_or_else(|| ImportError::Invalid("workspace member ids must be strings".to_owned()))?;
+    builder.set_attr(unit_of(id)?, "workspace", true);
+  }
+
+  let mut edges = BTreeMap::<(DepId, DepId), BTreeSet<DepKind>>::new();
+  match document.get("resolve") {
+    Some(resolve @ Json::Object(_)) => {
+      for node in field(resolve, "nodes", "resolve")?.as_array().unwrap_or_default() {
+        let unit = unit_of(string(node, "id", "node")?)?;
+        if let Some(deps) = node.get("deps").and_then(Json::as_array) {
+          for dep in deps {
+            let target = unit_of(string(dep, "pkg", "dependency")?)?;
+            let kinds = edges.entry((unit, target)).or_default();
+            for kind in dep.get("dep_kinds").and_then(Json::as_array).unwrap_or_default() {
+              kinds.insert(DepKind::parse(kind.get("kind").unwrap_or(&Json::Null))?);
+            }
+          }
+        } else {
+          // Cargo before 1.41 only lists the dependency ids.
+          for dep in node.get("dependencies").and_then(Json::as_array).unwrap_or_default() {
+            let id = dep.as_str().ok_or_else(|| ImportError::Invalid("dependency ids must be strings".to_owned()))?;
+            edges.entry((unit, unit_of(id)?)).or_default().insert(DepKind::Normal);
+          }
+        }
+      }
+    }
+    _ => {
+      for package in listed {
+        let unit = unit_of(string(package, "id", "package")?)?;
+        for dep in package.get("dependencies").and_then(Json::as_array).unwrap_or_default() {
+          let kind = DepKind::parse(dep.get("kind").unwrap_or(&Json::Null))?;
+          for target in packages.ids_of(string(dep, "name", "dependency")?) {
+            edges.entry((unit, target)).or_default().insert(kind);
+          }
+        }
+      }
+    }
+  }
+  for ((unit, dep), kinds) in edges {
+    builder.with_edge(unit, dep, kinds);
+  }
+  Ok((builder, packages))
+}
@@ -0,0 +1,176 @@
+use std::{fs, io::Read, path::Path};
+
+use super::{read_to_string, ImportError, ImportResult, Packages};
+use crate::DepTreeBuilder;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_SONAME: u64 = 14;
+
+/// Bounds-checked reads in the byte order and word size of one ELF file.
+struct Elf<'a> {
+  bytes: &'a [u8],
+  wide: bool,
+  big_endian: bool,
+}
+
+impl Elf<'_> {
+  fn int(&self, offset: u64, len: usize) -> Option<u64> {
+    let start = usize::try_from(offset).ok()?;
+    let bytes = self.bytes.get(start..start.checked_add(len)?)?;
+    let mut value = 0;
+    for i in 0..len {
+      let byte = if self.big_endian { bytes[i] } else { bytes[len - 1 - i] };
+      value = value << 8 | u64::from(byte);
+    }
+    Some(value)
+  }
+
+  /// An address-sized field.
+  fn word(&self, offset: u64) -> Option<u64> {
+    self.int(offset, if self.wide { 8 } else { 4 })
+  }
+
+  fn string(&self, offset: u64) -> Option<String> {
+    let rest = self.bytes.get(usize::try_from(offset).ok()?..)?;
+    let end = rest.iter().position(|&byte| byte == 0)?;
+    Some(String::from_utf8_lossy(&rest[..end]).into_owned())
+  }
+}
+
+/// `DT_SONAME` and `DT_NEEDED` entries of an ELF file, empty for static
+/// binaries.
+fn dynamic_entries(bytes: &[u8]) -> Result<(Option<String>, Vec<String>), &'static str> {
+  if bytes.get(..4) != Some(b"\x7fELF") {
+    return Err("not an ELF file");
+  }
+  let wide = match bytes.get(4) {
+    Some(1) => false,
+    Some(2) => true,
+    _ => return Err("unknown ELF class"),
+  };
+  let big_endian = match bytes.get(5) {
+    Some(1) => false,
+    Some(2) => true,
+    _ => return Err("unknown ELF byte order"),
+  };
+  let elf = Elf { bytes, wide, big_endian };
+  let truncated = "truncated ELF file";
+
+  let (phoff, phentsize, phnum) = if wide { (0x20, 0x36, 0x38) } else { (0x1c, 0x2a, 0x2c) };
+  let phoff = elf.word(phoff).ok_or(truncated)?;
+  let phentsize = elf.int(phentsize, 2).ok_or(truncated)?;
+  let phnum = elf.int(phnum, 2).ok_or(truncated)?;
+  // Offset, virtual address and file size of every loadable segment.
+  let mut loads = Vec::new();
+  let mut dynamic = None;
+  for index in 0..phnum {
+    let header = phoff.checked_add(index * phentsize).ok_or(truncated)?;
+    let kind = elf.int(header, 4).ok_or(truncated)?;
+    let fields = if wide { [8, 16, 32] } else { [4, 8, 16] };
+    let [offset, address, size] = fields.map(|field| elf.word(header.checked_add(field)?));
+    let segment = (offset.ok_or(truncated)?, address.ok_or(truncated)?, size.ok_or(truncated)?);
+    match kind as u32 {
+      PT_LOAD => loads.push(segment),
+      PT_DYNAMIC => dynamic = Some(segment),
+      _ => {}
+    }
+  }
+  let Some((offset, _, size)) = dynamic else {
+    return Ok((None, Vec::new()));
+  };
+
+  let entry_len = if wide { 16 } else { 8 };
+  let mut strtab = None;
+  let mut soname = None;
+  let mut needed = Vec::new();
+  for index in 0..size / entry_len {
+    let entry = offset.checked_add(index * entry_len).ok_or(truncated)?;
+    let tag = elf.word(entry).ok_or(truncated)?;
+    let value = entry.checked_add(entry_len / 2).and_then(|at| elf.word(at)).ok_or(truncated)?;
+    match tag {
+      0 => break,
+      DT_STRTAB => strtab = Some(value),
+      DT_SONAME => soname = Some(value),
+      DT_NEEDED => needed.push(value),
+      _ => {}
+    }
+  }
+  if soname.is_none() && needed.is_empty() {
+    return Ok((None, Vec::new()));
+  }
+  let strtab = strtab.ok_or("dynamic section without a string table")?;
+  let &(segment, address, _) = loads
+    .iter()
+    .find(|&&(_, address, size)| address <= strtab && strtab - address < size)
+    .ok_or("string table outside of every loaded segment")?;
+  let strtab = (strtab - address).checked_add(segment).ok_or(truncated)?;
+  let name = |at: u64| elf.string(strtab.checked_add(at).ok_or(truncated)?).ok_or(truncated);
+  Ok((soname.map(name).transpose()?, needed.into_iter().map(name).collect::<Result<_, _>>()?))
+}
+
+fn file_name(path: &str) -> &str {
+  path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Reads the `DT_NEEDED` entries of the given ELF binaries and shared
+/// libraries. Each file becomes a unit named by its `DT_SONAME`, or else its
+/// file name, that depends on the libraries it names. Pass the libraries
+/// along with the binaries to get their dependencies as well; nothing is
+/// looked up on the library search path. Every version is empty.
+pub fn elf_needed<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> ImportResult<(DepTreeBuilder, Packages)> {
+  let mut builder = DepTreeBuilder::new();
+  let mut packages = Packages::default();
+  for path in paths {
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+    let (soname, needed) = dynamic_entries(&bytes)
+      .map_err(|message| ImportError::Invalid(format!("{}: {message}", path.display())))?;
+    let name = soname.unwrap_or_else(|| path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned()));
+    let unit = packages.insert(&name, "");
+    let deps = needed.iter().map(|library| packages.insert(library, "")).collect();
+    builder.with_dep(unit, deps);
+  }
+  Ok((builder, packages))
+}
+
+/// Reads `ldd` output. `ldd` prints a `path:` header before each file's list
+/// when given several; lines before the first header belong to `binary`.
+/// Since `ldd` lists every library the loader maps, each file depends on all
+/// of them directly. Libraries are named by the file name `ldd` shows,
+/// including ones it could not find; the kernel's vDSO is skipped. Every
+/// version is empty.
+pub fn ldd(binary: &str, reader: impl Read) -> ImportResult<(DepTreeBuilder, Packages)> {
+  let text = read_to_string(reader)?;
+  let mut builder = DepTreeBuilder::new();
+  let mut packages = Packages::default();
+  let mut current = None;
+  for (index, line) in text.lines().enumerate() {
+    if !line.starts_with(char::is_whitespace) {
+      if let Some(path) = line.trim_end().strip_suffix(':') {
+        let unit = packages.insert(file_name(path), "");
+        builder.with_dep(unit, Vec::new());
+        current = Some(unit);
+        continue;
+      }
+    }
+    let line = line.trim();
+    if line.is_empty() || matches!(line, "statically linked" | "not a dynamic executable") {
+      continue;
+    }
+    let library = match line.split_once("=>") {
+      Some((name, _)) => name.trim(),
+      None if line.starts_with('/') => file_name(line.split(" (").next().unwrap_or(line)),
+      None if line.contains("vdso") => continue,
+      None => {
+        return Err(ImportError::Line { line: index + 1, message: format!("unexpected `ldd` line `{line}`") });
+      }
+    };
+    let unit = *current.get_or_insert_with(|| packages.insert(binary, ""));
+    let library = packages.insert(library, "");
+    builder.with_dep(unit, vec![library]);
+  }
+  Ok((builder, packages))
+}
@@ -0,0 +1,223 @@
+use std::{collections::BTreeMap, io::Read};
+
+use super::{read_to_string, ImportError, ImportResult};
+use crate::{
+  parse_label,
+  xml::{self, local_name, Event},
+  AttrValue, DepId, DepTreeBuilder,
+};
+
+/// Reads GraphML such as the output of `export::graphml` after a round trip
+/// through yEd or Gephi. A node's unit comes from its `unit`/`version` data
+/// (matched by attribute name, so renamed keys still work), then from an id of
+/// the form `(1, 0)`, then from a yEd node label of that form. The node's
+/// other data becomes its attributes, typed after the key's `attr.type`.
+/// Edges must connect declared or label-shaped nodes.
+pub fn graphml(reader: impl Read) -> ImportResult<DepTreeBuilder> {
+  let text = read_to_string(reader)?;
+  let events = xml::parse(&text)
+    .map_err(|(position, message)| ImportError::Syntax { position, message })?;
+  let mut keys = BTreeMap::new();
+  let mut units = BTreeMap::new();
+  let mut attrs = Vec::new();
+  let mut edges = Vec::new();
+  let mut node: Option<PendingNode> = None;
+  let mut capture: Option<(Option<String>, String)> = None;
+  for event in events {
+    match event {
+      Event::Start { name, attributes, empty } => match local_name(&name) {
+        "key" => {
+          if let Some(id) = Event::attribute(&attributes, "id") {
+            let key = Key {
+              name: Event::attribute(&attributes, "attr.name").unwrap_or(id).to_owned(),
+              kind: Event::attribute(&attributes, "attr.type").unwrap_or("string").to_owned(),
+            };
+            keys.insert(id.to_owned(), key);
+          }
+        }
+        "node" => {
+          let id = Event::attribute(&attributes, "id")
+            .ok_or_else(|| ImportError::Invalid("node without `id`".to_owned()))?;
+          node = Some(PendingNode {
+            id: id.to_owned(),
+            data: BTreeMap::new(),
+            label: None,
+          });
+          if empty {
+            finish_node(node.take(), &keys, &mut units, &mut attrs)?;
+          }
+        }
+        "edge" => {
+          let endpoint = |key: &str| {
+            Event::attribute(&attributes, key)
+              .map(str::to_owned)
+              .ok_or_else(|| ImportError::Invalid(format!("edge without `{key}`")))
+          };
+          edges.push((endpoint("source")?, endpoint("target")?));
+        }
+        "data" if node.is_some() && !empty => {
+          let key = Event::attribute(&attributes, "key").map(str::to_owned);
+          capture = Some((key, String::new()));
+        }
+        "NodeLabel" if node.is_some() && !empty => capture = Some((None, String::new())),
+        _ => {}
+      },
+      Event::Text(text) => {
+        if let Some((_, buffer)) = capture.as_mut() {
+          buffer.push_str(&text);
+        }
+      }
+      Event::End { name } => match local_name(&name) {
+        "data" | "NodeLabel" => {
+          if let (Some((key, buffer)), Some(node)) = (capture.take(), node.as_mut()) {
+            match key {
+              Some(key) => {
+                node.data.insert(key, buffer);
+              }
+              None => node.label = Some(buffer),
+            }
+          }
+        }
+        "node" => finish_node(node.take(), &keys, &mut units, &mut attrs)?,
+        _ => {}
+      },
+    }
+  }
+  let mut builder = DepTreeBuilder::new();
+  for &unit in units.values() {
+    builder.with_dep(unit, Vec::new());
+  }
+  for (unit, key, value) in attrs {
+    builder.set_attr(unit, key, value);
+  }
+  for (source, target) in edges {
+    let resolve = |id: &str| {
+      units
+        .get(id)
+        .copied()
+        .or_else(|| parse_label(id))
+        .ok_or_else(|| ImportError::UnknownNode(id.to_owned()))
+    };
+    builder.with_dep(resolve(&source)?, vec![resolve(&target)?]);
+  }
+  Ok(builder)
+}
+
+/// Declared `key` element.
+struct Key {
+  /// `attr.name`, or the key id when there is none.
+  name: String,
+  kind: String,
+}
+
+impl Key {
+  /// `text` as an attribute of this key's type, kept as a string when it
+  /// doesn't parse as one.
+  fn value(&self, text: &str) -> AttrValue {
+    let parsed = match self.kind.as_str() {
+      "int" | "long" => text.trim().parse::<i64>().ok().map(AttrValue::Int),
+      "boolean" => text.trim().parse::<bool>().ok().map(AttrValue::Bool),
+      _ => None,
+    };
+    parsed.unwrap_or_else(|| AttrValue::from(text))
+  }
+}
+
+struct PendingNode {
+  id: String,
+  /// Text of every `data` element by key id.
+  data: BTreeMap<String, String>,
+  label: Option<String>,
+}
+
+fn finish_node(
+  node: Option<PendingNode>,
+  keys: &BTreeMap<String, Key>,
+  units: &mut BTreeMap<String, DepId>,
+  attrs: &mut Vec<(DepId, String, AttrValue)>,
+) -> ImportResult<()> {
+  let Some(PendingNode { id, data, label }) = node else {
+    return Ok(());
+  };
+  let name = |key: &str| keys.get(key).map_or(key, |key| key.name.as_str()).to_owned();
+  let field = |wanted: &str| {
+    data
+      .iter()
+      .find(|(key, _)| name(key) == wanted)
+      .and_then(|(_, value)| value.trim().parse::<u64>().ok())
+  };
+  let unit = match (field("unit"), field("version")) {
+    (Some(unit), Some(version)) => Some((unit, version as usize)),
+    _ => parse_label(&id).or_else(|| label.as_deref().and_then(parse_label)),
+  };
+  let unit = unit.ok_or_else(|| ImportError::UnknownNode(id.clone()))?;
+  for (key, text) in &data {
+    let name = name(key);
+    if name != "unit" && name != "version" {
+      let value = keys.get(key).map_or_else(|| AttrValue::from(text.as_str()), |key| key.value(text));
+      attrs.push((unit, name, value));
+    }
+  }
+  units.insert(id, unit);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{export, DepTree};
+
+  fn deps(tree: &DepTree) -> Vec<(DepId, Vec<DepId>)> {
+    tree.reverse_map().into_keys().map(|unit| (unit, tree.direct(unit).to_vec())).collect()
+  }
+
+  #[test]
+  fn round_trips_export() {
+    let mut builder = DepTreeBuilder::new();
+    builder.with_dep((1, 0), vec![(2, 0), (3, 1)]);
+    builder.with_dep((2, 0), vec![(3, 1)]);
+    builder.set_attr((1, 0), "note", "<a & \"b\">");
+    builder.set_attr((1, 0), "code", "007");
+    builder.set_attr((2, 0), "size", -4);
+    builder.set_attr((3, 1), "optional", true);
+    builder.set_attr((3, 1), "version", 99);
+    let tree = *builder.build().unwrap();
+    let mut bytes = Vec::new();
+    export::graphml(&tree, &mut bytes).unwrap();
+    let read_back = *graphml(&bytes[..]).unwrap().build().unwrap();
+    assert_eq!(deps(&read_back), deps(&tree));
+    let attrs = |unit| read_back.attrs(unit).map(|(key, value)| (key.to_owned(), value.clone())).collect::<Vec<_>>();
+    let note = AttrValue::from("<a & \"b\">");
+    assert_eq!(attrs((1, 0)), [("code".to_owned(), AttrValue::from("007")), ("note".to_owned(), note)]);
+    assert_eq!(attrs((2, 0)), [("size".to_owned(), AttrValue::Int(-4))]);
+    assert_eq!(attrs((3, 1)), [("optional".to_owned(), AttrValue::Bool(true))]);
+  }
+
+  #[test]
+  fn reads_renamed_keys_and_labels() {
+    let text = r#"<graphml xmlns:y="http://www.yworks.com/xml/graphml">
+      <key id="d0" for="node" attr.name="unit" attr.type="long"/>
+      <key id="d1" for="node" attr.name="version" attr.type="long"/>
+      <key id="d2" for="node" attr.name="weight" attr.type="int"/>
+      <graph edgedefault="directed">
+        <node id="n0"><data key="d0">1</data><data key="d1">0</data><data key="d2">x</data></node>
+        <node id="n1"><data key="d3"><y:ShapeNode><y:NodeLabel>(2, 0)</y:NodeLabel></y:ShapeNode></data></node>
+        <edge source="n0" target="n1"/>
+        <edge source="n1" target="(3, 0)"/>
+      </graph>
+    </graphml>"#;
+    let tree = *graphml(text.as_bytes()).unwrap().build().unwrap();
+    assert_eq!(deps(&tree), [((1, 0), vec![(2, 0)]), ((2, 0), vec![(3, 0)]), ((3, 0), Vec::new())]);
+    assert_eq!(tree.attr((1, 0), "weight"), Some(&AttrValue::from("x")));
+  }
+
+  #[test]
+  fn rejects_unknown_nodes() {
+    let error = |text: &str| graphml(text.as_bytes()).unwrap_err().to_string();
+    assert_eq!(error(r#"<graphml><graph><node id="a"/></graph></graphml>"#), "node `a` has no unit id");
+    let text = r#"<graphml><graph><node id="(1, 0)"/><edge source="(1, 0)" target="b"/></graph></graphml>"#;
+    assert_eq!(error(text), "node `b` has no unit id");
+    let text = r#"<graphml><graph><edge source="a"/></graph></graphml>"#;
+    assert_eq!(error(text), "invalid document: edge without `target`");
+  }
+}
@@ -0,0 +1,127 @@
+use std::{collections::BTreeMap, io::Read};
+
+use super::{read_to_string, ImportError, ImportResult};
+use crate::{json::Json, parse_label, DepId, DepTreeBuilder};
+
+/// Reads a JSON Graph Format document, either the v2 layout with `nodes` keyed
+/// by id or the v1 layout with a `nodes` array. Units are taken from the
+/// `unit`/`version` metadata written by `export::jgf`, falling back to node
+/// ids of the form `(1, 0)`. Other string, integer and boolean metadata
+/// becomes node attributes. Every graph of a `graphs` document is merged.
+pub fn jgf(reader: impl Read) -> ImportResult<DepTreeBuilder> {
+  let text = read_to_string(reader)?;
+  let document = Json::parse(&text)
+    .map_err(|(position, message)| ImportError::Syntax { position, message })?;
+  let graphs = match (document.get("graph"), document.get("graphs")) {
+    (Some(graph), _) => vec![graph],
+    (None, Some(Json::Array(graphs))) => graphs.iter().collect(),
+    _ => return Err(ImportError::Invalid("expected `graph` or `graphs`".to_owned())),
+  };
+  let mut builder = DepTreeBuilder::new();
+  for graph in graphs {
+    let mut units = BTreeMap::new();
+    let nodes = match graph.get("nodes") {
+      Some(Json::Object(nodes)) => nodes.iter().map(|(id, node)| (id.as_str(), node)).collect(),
+      Some(Json::Array(nodes)) => nodes
+        .iter()
+        .map(|node| {
+          let id = node.get("id").and_then(Json::as_str);
+          id.map(|id| (id, node))
+            .ok_or_else(|| ImportError::Invalid("node without `id`".to_owned()))
+        })
+        .collect::<ImportResult<Vec<_>>>()?,
+      None => Vec::new(),
+      Some(_) => return Err(ImportError::Invalid("`nodes` must be an object or array".to_owned())),
+    };
+    for (id, node) in nodes {
+      let unit = node_unit(id, node).ok_or_else(|| ImportError::UnknownNode(id.to_owned()))?;
+      units.insert(id, unit);
+      builder.with_dep(unit, Vec::new());
+      if let Some(Json::Object(metadata)) = node.get("metadata") {
+        for (key, value) in metadata.iter().filter(|(key, _)| key != "unit" && key != "version") {
+          if let Some(value) = value.as_attr() {
+            builder.set_attr(unit, key.as_str(), value);
+          }
+        }
+      }
+    }
+    let edges = graph.get("edges").and_then(Json::as_array).unwrap_or_default();
+    for edge in edges {
+      let endpoint = |key: &str| -> ImportResult<DepId> {
+        let id = edge
+          .get(key)
+          .and_then(Json::as_str)
+          .ok_or_else(|| ImportError::Invalid(format!("edge without `{key}`")))?;
+        units
+          .get(id)
+          .copied()
+          .or_else(|| parse_label(id))
+          .ok_or_else(|| ImportError::UnknownNode(id.to_owned()))
+      };
+      builder.with_dep(endpoint("source")?, vec![endpoint("target")?]);
+    }
+  }
+  Ok(builder)
+}
+
+fn node_unit(id: &str, node: &Json) -> Option<DepId> {
+  let metadata = node.get("metadata");
+  let field = |key: &str| metadata.and_then(|metadata| metadata.get(key)).and_then(Json::as_u64);
+  match (field("unit"), field("version")) {
+    (Some(unit), Some(version)) => Some((unit, version as usize)),
+    _ => parse_label(id),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{export, AttrValue, DepTree};
+
+  fn tree() -> DepTree {
+    let mut builder = DepTreeBuilder::new();
+    builder.with_dep((1, 0), vec![(2, 0), (3, 1)]);
+    builder.with_dep((2, 0), vec![(3, 1)]);
+    builder.set_attr((1, 0), "note", "\"quoted\"");
+    builder.set_attr((2, 0), "size", -4);
+    builder.set_attr((3, 1), "optional", true);
+    builder.set_attr((3, 1), "unit", 99);
+    *builder.build().unwrap()
+  }
+
+  fn deps(tree: &DepTree) -> Vec<(DepId, Vec<DepId>)> {
+    tree.reverse_map().into_keys().map(|unit| (unit, tree.direct(unit).to_vec())).collect()
+  }
+
+  #[test]
+  fn round_trips_export() {
+    let tree = tree();
+    let mut bytes = Vec::new();
+    export::jgf(&tree, &mut bytes).unwrap();
+    let read_back = *jgf(&bytes[..]).unwrap().build().unwrap();
+    assert_eq!(deps(&read_back), deps(&tree));
+    assert_eq!(read_back.attrs((1, 0)).collect::<Vec<_>>(), [("note", &AttrValue::from("\"quoted\""))]);
+    assert_eq!(read_back.attrs((2, 0)).collect::<Vec<_>>(), [("size", &AttrValue::Int(-4))]);
+    assert_eq!(read_back.attrs((3, 1)).collect::<Vec<_>>(), [("optional", &AttrValue::Bool(true))]);
+  }
+
+  #[test]
+  fn reads_v1_and_multiple_graphs() {
+    let text = r#"{"graphs": [
+      {"nodes": [{"id": "a", "metadata": {"unit": 1, "version": 0}}, {"id": "(2, 0)"}],
+       "edges": [{"source": "a", "target": "(2, 0)"}]},
+      {"edges": [{"source": "(2, 0)", "target": "(3, 0)"}]}
+    ]}"#;
+    let tree = *jgf(text.as_bytes()).unwrap().build().unwrap();
+    assert_eq!(deps(&tree), [((1, 0), vec![(2, 0)]), ((2, 0), vec![(3, 0)]), ((3, 0), Vec::new())]);
+  }
+
+  #[test]
+  fn rejects_unknown_nodes() {
+    let error = |text: &str| jgf(text.as_bytes()).unwrap_err().to_string();
+    assert_eq!(error(r#"{"nodes": {}}"#), "invalid document: expected `graph` or `graphs`");
+    assert_eq!(error(r#"{"graph": {"nodes": {"a": {}}}}"#), "node `a` has no unit id");
+    assert_eq!(error(r#"{"graph": {"edges": [{"source": "b", "target": "(1, 0)"}]}}"#), "node `b` has no unit id");
+    assert_eq!(error(r#"{"graph": [}"#), "syntax error at 11: unexpected token");
+  }
+}
@@ -0,0 +1,132 @@
+use std::{collections::BTreeSet, io::Read};
+
+use super::{read_to_string, ImportError, ImportResult, Packages};
+use crate::{DepId, DepTreeBuilder};
+
+/// State shared by the tree readers. `stack[depth]` is the unit last seen at
+/// that depth, the one a deeper entry is a dependency of.
+#[derive(Default)]
+struct TreeReader {
+  builder: DepTreeBuilder,
+  packages: Packages,
+  stack: Vec<DepId>,
+  edges: BTreeSet<(DepId, DepId)>,
+}
+
+impl TreeReader {
+  fn push(&mut self, line: usize, depth: usize, name: &str, version: &str) -> ImportResult<DepId> {
+    if depth > self.stack.len() {
+      return Err(ImportError::Line { line, message: "entry is nested too deeply".to_owned() });
+    }
+    self.stack.truncate(depth);
+    let unit = self.packages.insert(name, version);
+    match self.stack.last() {
+      Some(&parent) if self.edges.insert((parent, unit)) => {
+        self.builder.with_dep(parent, vec![unit]);
+      }
+      Some(_) => {}
+      None => {
+        self.builder.with_dep(unit, Vec::new());
+      }
+    }
+    self.stack.push(unit);
+    Ok(unit)
+  }
+}
+
+/// Number of `width`-wide indentation segments before the `+`/`\` branch of a
+/// tree line, with the text after the branch.
+fn branch<'a>(line: &'a str, indent: [&str; 2], branches: [&str; 2]) -> Option<(usize, &'a str)> {
+  let width = indent[0].len();
+  let mut depth = 0;
+  let mut rest = line;
+  while indent.iter().any(|segment| rest.starts_with(segment)) {
+    rest = &rest[width..];
+    depth += 1;
+  }
+  branches.iter().find_map(|branch| rest.strip_prefix(branch)).map(|entry| (depth, entry))
+}
+
+/// Reads the output of `mvn dependency:tree`, with or without the `[INFO]`
+/// prefixes and surrounding log lines. Each module's root artifact depends on
+/// the entries below it. Packages are named `groupId:artifactId`, plus
+/// `:classifier` when there is one, and every unit gets its Maven scope as a
+/// `scope` attribute. Entries `-Dverbose` marks as omitted still add their
+/// edge.
+pub fn maven_tree(reader: impl Read) -> ImportResult<(DepTreeBuilder, Packages)> {
+  let text = read_to_string(reader)?;
+  let mut tree = TreeReader::default();
+  for (index, line) in text.lines().enumerate() {
+    let line_number = index + 1;
+    let line = line.strip_prefix("[INFO]").map_or(line, |line| line.strip_prefix(' ').unwrap_or(line));
+    let (depth, entry) = match branch(line, ["|  ", "   "], ["+- ", "\\- "]) {
+      Some((depth, entry)) => (depth + 1, entry),
+      None if !line.contains(char::is_whitespace) && line.split(':').count() >= 4 => (0, line),
+      None => continue,
+    };
+    let coordinate = entry.trim_start_matches('(').split([' ', ')']).next().unwrap_or_default();
+    let parts = coordinate.split(':').collect::<Vec<_>>();
+    let (name, version, scope) = match (depth, parts.as_slice()) {
+      (0, [group, artifact, _, version]) => (format!("{group}:{artifact}"), *version, None),
+      (0, [group, artifact, _, classifier, version]) => (format!("{group}:{artifact}:{classifier}"), *version, None),
+      (_, [group, artifact, _, version, scope]) if depth > 0 => (format!("{group}:{artifact}"), *version, Some(*scope)),
+      (_, [group, artifact, _, classifier, version, scope]) if depth > 0 => {
+        (format!("{group}:{artifact}:{classifier}"), *version, Some(*scope))
+      }
+      _ => {
+        return Err(ImportError::Line { line: line_number, message: format!("invalid artifact `{coordinate}`") });
+      }
+    };
+    let unit = tree.push(line_number, depth, &name, version)?;
+    if let Some(scope) = scope {
+      tree.builder.set_attr(unit, "scope", scope);
+    }
+  }
+  Ok((tree.builder, tree.packages))
+}
+
+/// Reads the output of `gradle dependencies`. Every configuration's tree is
+/// merged, with its top-level entries as roots since the report does not name
+/// the project they belong to. Packages are named `group:artifact` at the
+/// version Gradle selected, the one after `->`, or `:path` for project
+/// dependencies with an empty version. Constraint entries marked `(c)` are
+/// skipped.
+pub fn gradle_dependencies(reader: impl Read) -> ImportResult<(DepTreeBuilder, Packages)> {
+  let text = read_to_string(reader)?;
+  let mut tree = TreeReader::default();
+  for (index, line) in text.lines().enumerate() {
+    let line_number = index + 1;
+    let Some((depth, entry)) = branch(line, ["|    ", "     "], ["+--- ", "\\--- "]) else {
+      if !line.trim().is_empty() && !line.starts_with(' ') {
+        tree.stack.clear();
+      }
+      continue;
+    };
+    let mut entry = entry.trim();
+    if entry.ends_with(" (c)") {
+      continue;
+    }
+    for marker in [" (*)", " (n)"] {
+      entry = entry.strip_suffix(marker).unwrap_or(entry);
+    }
+    let (coordinate, selected) = match entry.split_once(" -> ") {
+      Some((coordinate, selected)) => (coordinate.trim(), Some(selected.trim())),
+      None => (entry, None),
+    };
+    let (name, requested) = if let Some(path) = coordinate.strip_prefix("project ") {
+      (path.to_owned(), "")
+    } else {
+      let mut parts = coordinate.splitn(3, ':');
+      match (parts.next(), parts.next(), parts.next()) {
+        (Some(group), Some(artifact), version) if !group.is_empty() && !artifact.is_empty() => {
+          (format!("{group}:{artifact}"), version.unwrap_or_default())
+        }
+        _ => {
+          return Err(ImportError::Line { line: line_number, message: format!("invalid dependency `{coordinate}`") });
+        }
+      }
+    };
+    tree.push(line_number, depth, &name, selected.unwrap_or(requested))?;
+  }
+  Ok((tree.builder, tree.packages))
+}
@@ -0,0 +1,160 @@
+use std::io::Read;
+
+use super::{read_to_string, ImportError, ImportResult};
+use crate::{DepId, DepTreeBuilder};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+  Integer(u64),
+  Array(Vec<Value>),
+}
+
+/// Reads a TOML manifest made of `[[unit]]` tables:
+///
+/// ```toml
+/// [[unit]]
+/// id = 1
+/// version = 0            # optional, defaults to 0
+/// deps = [[2, 0], [3, 1]] # optional, `[id, version]` pairs
+/// ```
+///
+/// Only the subset of TOML needed for this layout is understood: comments,
+/// integers and (possibly multi-line) arrays.
+pub fn manifest_toml(reader: impl Read) -> ImportResult<DepTreeBuilder> {
+  let text = read_to_string(reader)?;
+  let mut builder = DepTreeBuilder::new();
+  let mut current: Option<PendingUnit> = None;
+  let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, strip_comment(line)));
+  while let Some((line_number, line)) = lines.next() {
+    let error = |message: &str| ImportError::Line {
+      line: line_number,
+      message: message.to_owned(),
+    };
+    let line = line.trim();
+    if line.is_empty() {
+      continue;
+    }
+    if line.starts_with('[') && !line.contains('=') {
+      if line != "[[unit]]" {
+        return Err(error("only `[[unit]]` tables are supported"));
+      }
+      if let Some(unit) = current.take() {
+        unit.finish(&mut builder)?;
+      }
+      current = Some(PendingUnit {
+        line: line_number,
+        ..Default::default()
+      });
+      continue;
+    }
+    let unit = current.as_mut().ok_or_else(|| error("key outside of a `[[unit]]` table"))?;
+    let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+    let mut value = value.trim().to_owned();
+    while depth(&value) > 0 {
+      let (_, next) = lines.next().ok_or_else(|| error("unterminated array"))?;
+      value.push(' ');
+      value.push_str(next.trim());
+    }
+    let (value, rest) = parse_value(&value).map_err(|message| error(&message))?;
+    if !rest.trim().is_empty() {
+      return Err(error("unexpected characters after value"));
+    }
+    match (key.trim(), value) {
+      ("id", Value::Integer(id)) => unit.id = Some(id),
+      ("version", Value::Integer(version)) => unit.version = version as usize,
+      ("deps", Value::Array(deps)) => {
+        for dep in deps {
+          match dep {
+            Value::Array(pair) => match pair.as_slice() {
+              [Value::Integer(id), Value::Integer(version)] => {
+                unit.deps.push((*id, *version as usize));
+              }
+              _ => return Err(error("dependencies must be `[id, version]` pairs")),
+            },
+            _ => return Err(error("dependencies must be `[id, version]` pairs")),
+          }
+        }
+      }
+      ("id" | "version", _) => return Err(error("expected an integer")),
+      ("deps", _) => return Err(error("expected an array")),
+      _ => return Err(error("unknown key")),
+    }
+  }
+  if let Some(unit) = current {
+    unit.finish(&mut builder)?;
+  }
+  Ok(builder)
+}
+
+#[derive(Default)]
+struct PendingUnit {
+  line: usize,
+  id: Option<u64>,
+  version: usize,
+  deps: Vec<DepId>,
+}
+
+impl PendingUnit {
+  fn finish(self, builder: &mut DepTreeBuilder) -> ImportResult<()> {
+    let id = self.id.ok_or_else(|| ImportError::Line {
+      line: self.line,
+      message: "unit without `id`".to_owned(),
+    })?;
+    builder.with_dep((id, self.version), self.deps);
+    Ok(())
+  }
+}
+
+fn strip_comment(line: &str) -> &str {
+  let mut in_string = false;
+  for (i, c) in line.char_indices() {
+    match c {
+      '"' => in_string = !in_string,
+      '#' if !in_string => return &line[..i],
+      _ => {}
+    }
+  }
+  line
+}
+
+/// Unclosed `[` count outside of strings.
+fn depth(text: &str) -> isize {
+  let mut in_string = false;
+  let mut depth = 0;
+  for c in text.chars() {
+    match c {
+      '"' => in_string = !in_string,
+      '[' if !in_string => depth += 1,
+      ']' if !in_string => depth -= 1,
+      _ => {}
+    }
+  }
+  depth
+}
+
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+  let text = text.trim_start();
+  if let Some(mut rest) = text.strip_prefix('[') {
+    let mut items = Vec::new();
+    loop {
+      rest = rest.trim_start();
+      if let Some(rest) = rest.strip_prefix(']') {
+        return Ok((Value::Array(items), rest));
+      }
+      let (item, after) = parse_value(rest)?;
+      items.push(item);
+      rest = after.trim_start();
+      if let Some(after) = rest.strip_prefix(',') {
+        rest = after;
+      } else if !rest.starts_with(']') {
+        return Err("expected `,` or `]`".to_owned());
+      }
+    }
+  }
+  let end = text
+    .find(|c: char| !(c.is_ascii_digit() || c == '_'))
+    .unwrap_or(text.len());
+  let digits = text[..end].replace('_', "");
+  let value = digits.parse().map_err(|_| format!("invalid value `{}`", text.trim()))?;
+  Ok((Value::Integer(value), &text[end..]))
+}
@@ -0,0 +1,285 @@
+use std::{collections::BTreeMap, io::Read};
+
+use super::{read_to_string, ImportError, ImportResult, Packages};
+use crate::{DepId, DepTreeBuilder};
+
+fn line_error(line: usize, message: &str) -> ImportError {
+  ImportError::Line { line, message: message.to_owned() }
+}
+
+/// Normalizes a distribution name as PEP 503 does: lowercase, with runs of
+/// `-`, `_` and `.` replaced by a single `-`.
+fn normalize(name: &str) -> String {
+  let mut normalized = String::with_capacity(name.len());
+  for c in name.chars() {
+    if matches!(c, '-' | '_' | '.') {
+      if !normalized.ends_with('-') {
+        normalized.push('-');
+      }
+    } else {
+      normalized.push(c.to_ascii_lowercase());
+    }
+  }
+  normalized
+}
+
+/// Reads a pinned `requirements.txt`, one unit per `name==version` line.
+/// Edges come from the `# via` annotations `pip-compile` writes below each
+/// pin or after it on the same line, so a file without them yields isolated units. Line continuations,
+/// extras, environment markers and `--hash` options are accepted and
+/// ignored, as are option lines such as `-r` and `--index-url`. Names are
+/// normalized as in PEP 503.
+pub fn requirements_txt(reader: impl Read) -> ImportResult<(DepTreeBuilder, Packages)> {
+  let text = read_to_string(reader)?;
+  let mut builder = DepTreeBuilder::new();
+  let mut packages = Packages::default();
+  let mut pins = BTreeMap::new();
+  let mut via = Vec::new();
+  let mut current = None;
+  let mut in_via = false;
+  let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+  while let Some((line_number, line)) = lines.next() {
+    let mut line = line.trim().to_owned();
+    while line.ends_with('\\') {
+      line.pop();
+      let Some((_, next)) = lines.next() else {
+        break;
+      };
+      line.push(' ');
+      line.push_str(next.trim());
+    }
+
+    if let Some(comment) = line.strip_prefix('#') {
+      let comment = comment.trim();
+      let mut add_via = |name: &str| {
+        if let (Some(unit), false) = (current, name.starts_with('-')) {
+          via.push((unit, normalize(name)));
+        }
+      };
+      if comment == "via" {
+        in_via = true;
+      } else if let Some(name) = comment.strip_prefix("via ") {
+        in_via = false;
+        add_via(name.trim());
+      } else if in_via && !comment.is_empty() {
+        add_via(comment);
+      }
+      continue;
+    }
+    in_via = false;
+    let (requirement, comment) = line.find(" #").map_or((line.as_str(), ""), |end| (&line[..end], &line[end + 2..]));
+    let requirement = requirement.trim();
+    if requirement.is_empty() {
+      continue;
+    }
+    if requirement.starts_with('-') {
+      current = None;
+      continue;
+    }
+    let (name, version) = parse_pin(requirement).map_err(|message| line_error(line_number, &message))?;
+    let unit = packages.insert(&name, version);
+    builder.with_dep(unit, Vec::new());
+    pins.insert(name, unit);
+    current = Some(unit);
+    // `pip-compile --annotation-style=line` puts the annotation on the pin.
+    if let Some(names) = comment.trim().strip_prefix("via ") {
+      for name in names.split(',').map(str::trim).filter(|name| !name.is_empty() && !name.starts_with('-')) {
+        via.push((unit, normalize(name)));
+      }
+    }
+  }
+
+  // An annotation may name a dependent that is only pinned further down.
+  for (unit, dependent) in via {
+    if let Some(&dependent) = pins.get(&dependent) {
+      builder.with_dep(dependent, vec![unit]);
+    }
+  }
+  Ok((builder, packages))
+}
+
+/// Normalized name and version of `name[extras]==version`, ignoring anything
+/// after the version.
+fn parse_pin(requirement: &str) -> Result<(String, &str), String> {
+  let end = requirement
+    .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    .unwrap_or(requirement.len());
+  if end == 0 {
+    return Err(format!("invalid requirement `{requirement}`"));
+  }
+  let name = normalize(&requirement[..end]);
+  let mut rest = requirement[end..].trim_start();
+  if rest.starts_with('[') {
+    let close = rest.find(']').ok_or_else(|| format!("unterminated extras in `{requirement}`"))?;
+    rest = rest[close + 1..].trim_start();
+  }
+  let version = rest
+    .strip_prefix("===")
+    .or_else(|| rest.strip_prefix("=="))
+    .ok_or_else(|| format!("`{name}` is not pinned with `==`"))?
+    .trim_start();
+  let version = &version[..version.find(|c: char| c.is_whitespace() || matches!(c, ';' | ',')).unwrap_or(version.len())];
+  if version.is_empty() {
+    return Err(format!("`{name}` has an empty version"));
+  }
+  Ok((name, version))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+  Package,
+  Dependencies,
+  Other,
+}
+
+struct LockedPackage {
+  line: usize,
+  name: Option<String>,
+  version: Option<String>,
+  deps: Vec<String>,
+}
+
+/// Reads a `poetry.lock`, one unit per `[[package]]` table with edges from
+/// its `[package.dependencies]`. A dependency resolves to every locked
+/// version of that name, so packages locked twice under different markers
+/// are both depended on. Dependencies missing from the lock, such as extras
+/// nobody requested, are skipped. Names are normalized as in PEP 503.
+///
+/// Only as much TOML is understood as poetry writes: tables, quoted and bare
+/// keys, strings, and arrays or inline tables spanning several lines.
+pub fn poetry_lock(reader: impl Read) -> ImportResult<(DepTreeBuilder, Packages)> {
+  let text = read_to_string(reader)?;
+  let mut locked: Vec<LockedPackage> = Vec::new();
+  let mut section = Section::Other;
+  let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+  while let Some((line_number, line)) = lines.next() {
+    let (line, _) = scan(line);
+    let line = line.trim();
+    if line.is_empty() {
+      continue;
+    }
+    if line.starts_with('[') {
+      let header = line.replace(' ', "");
+      section = match header.as_str() {
+        "[[package]]" => {
+          locked.push(LockedPackage { line: line_number, name: None, version: None, deps: Vec::new() });
+          Section::Package
+        }
+        "[package.dependencies]" if !locked.is_empty() => Section::Dependencies,
+        "[package.dependencies]" => return Err(line_error(line_number, "dependencies outside of a `[[package]]` table")),
+        _ => Section::Other,
+      };
+      continue;
+    }
+
+    let (key, value) = split_key(line).ok_or_else(|| line_error(line_number, "expected `key = value`"))?;
+    let mut value = value.to_owned();
+    if value.starts_with("\"\"\"") && !value[3..].contains("\"\"\"") {
+      loop {
+        let (_, next) = lines.next().ok_or_else(|| line_error(line_number, "unterminated string"))?;
+        if next.contains("\"\"\"") {
+          break;
+        }
+      }
+    } else {
+      let mut depth = scan(&value).1;
+      while depth > 0 {
+        let (_, next) = lines.next().ok_or_else(|| line_error(line_number, "unterminated value"))?;
+        let (next, change) = scan(next);
+        value.push(' ');
+        value.push_str(next.trim());
+        depth += change;
+      }
+    }
+
+    let Some(package) = locked.last_mut() else {
+      continue;
+    };
+    match (section, key.as_str()) {
+      (Section::Package, "name") => {
+        let name = string(&value).ok_or_else(|| line_error(line_number, "`name` must be a string"))?;
+        package.name = Some(normalize(&name));
+      }
+      (Section::Package, "version") => {
+        package.version = Some(string(&value).ok_or_else(|| line_error(line_number, "`version` must be a string"))?);
+      }
+      (Section::Dependencies, _) => package.deps.push(normalize(&key)),
+      _ => {}
+    }
+  }
+
+  let mut builder = DepTreeBuilder::new();
+  let mut packages = Packages::default();
+  let mut units = Vec::new();
+  for package in &locked {
+    let (Some(name), Some(version)) = (&package.name, &package.version) else {
+      return Err(line_error(package.line, "package without `name` and `version`"));
+    };
+    let unit = packages.insert(name, version);
+    builder.with_dep(unit, Vec::new());
+    units.push(unit);
+  }
+  for (package, unit) in locked.iter().zip(units) {
+    let deps = package.deps.iter().flat_map(|dep| packages.ids_of(dep)).collect::<Vec<DepId>>();
+    builder.with_dep(unit, deps);
+  }
+  Ok((builder, packages))
+}
+
+/// Splits off a trailing comment and returns the rest with the change in
+/// `[`/`{` nesting across it, skipping over strings.
+fn scan(line: &str) -> (&str, isize) {
+  let mut depth = 0;
+  let mut quote = None;
+  let mut escaped = false;
+  for (i, c) in line.char_indices() {
+    match quote {
+      Some(_) if escaped => escaped = false,
+      Some('"') if c == '\\' => escaped = true,
+      Some(open) if c == open => quote = None,
+      Some(_) => {}
+      None => match c {
+        '"' | '\'' => quote = Some(c),
+        '[' | '{' => depth += 1,
+        ']' | '}' => depth -= 1,
+        '#' => return (&line[..i], depth),
+        _ => {}
+      },
+    }
+  }
+  (line, depth)
+}
+
+/// Key and raw value of a `key = value` line; the key may be quoted.
+fn split_key(line: &str) -> Option<(String, &str)> {
+  let (key, rest) = if line.starts_with(['"', '\'']) {
+    let end = line[1..].find(line.as_bytes()[0] as char)? + 1;
+    (line[1..end].to_owned(), &line[end + 1..])
+  } else {
+    let end = line.find('=')?;
+    (line[..end].trim().to_owned(), &line[end..])
+  };
+  Some((key, rest.trim_start().strip_prefix('=')?.trim()))
+}
+
+/// Contents of a basic or literal single-line string.
+fn string(value: &str) -> Option<String> {
+  if let Some(literal) = value.strip_prefix('\'') {
+    return Some(literal[..literal.find('\'')?].to_owned());
+  }
+  let mut chars = value.strip_prefix('"')?.chars();
+  let mut text = String::new();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => return Some(text),
+      '\\' => match chars.next()? {
+        'n' => text.push('\n'),
+        't' => text.push('\t'),
+        'r' => text.push('\r'),
+        other => text.push(other),
+      },
+      c => text.push(c),
+    }
+  }
+  None
+}
@@ -0,0 +1,113 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{DepId, DepTree};
+
+/// Closure of a set of vendored targets, split by whether anything else needs
+/// the same units. Both sets together are the whole closure.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VendorSet {
+  /// Units only the targets need, targets included.
+  pub exclusive: BTreeSet<DepId>,
+  /// Units also reached from some unit outside the closure.
+  pub shared: BTreeSet<DepId>,
+}
+
+impl DepTree {
+  /// Targets and their transitive dependencies, each unit at most once.
+  fn closure_of(&self, targets: &[DepId]) -> BTreeSet<DepId> {
+    let mut closure = BTreeSet::new();
+    let mut stack = targets.to_vec();
+    while let Some(id) = stack.pop() {
+      if closure.insert(id) {
+        stack.extend_from_slice(self.direct(id));
+      }
+    }
+    closure
+  }
+
+  /// Everything `targets` need, themselves included, partitioned into the
+  /// units nothing outside that closure depends on and the ones it shares.
+  pub fn vendor_set(&self, targets: &[DepId]) -> VendorSet {
+    let closure = self.closure_of(targets);
+    let mut reached = BTreeSet::new();
+    let mut stack = self
+      .inner
+      .iter()
+      .filter(|(id, _)| !closure.contains(id))
+      .flat_map(|(_, deps)| deps.iter().copied())
+      .collect::<Vec<_>>();
+    while let Some(id) = stack.pop() {
+      if reached.insert(id) {
+        stack.extend_from_slice(self.direct(id));
+      }
+    }
+    let (shared, exclusive) = closure.into_iter().partition(|id| reached.contains(id));
+    VendorSet { exclusive, shared }
+  }
+
+  /// Targets and their transitive dependencies with every unit after all of
+  /// its dependencies, so units can be installed front to back. Depth-first
+  /// from each target in the order given.
+  pub fn install_order(&self, targets: &[DepId]) -> Vec<DepId> {
+    let mut visited = BTreeSet::new();
+    let mut order = Vec::new();
+    for &target in targets {
+      self.post_order(target, &mut visited, &mut order);
+    }
+    order
+  }
+
+  /// Targets ordered so every unit comes before its dependencies, so removing
+  /// them front to back never leaves a unit without something it needs. Fails
+  /// with the direct dependents outside `targets` of every target that is
+  /// still required.
+  pub fn removal_order(&self, targets: &[DepId]) -> Result<Vec<DepId>, BTreeMap<DepId, Vec<DepId>>> {
+    let removed = targets.iter().copied().collect::<BTreeSet<_>>();
+    let mut blockers = BTreeMap::<DepId, Vec<DepId>>::new();
+    for (&id, deps) in self.inner.iter().filter(|(id, _)| !removed.contains(id)) {
+      for dep in deps.iter().filter(|dep| removed.contains(dep)) {
+        let dependents = blockers.entry(*dep).or_default();
+        if !dependents.contains(&id) {
+          dependents.push(id);
+        }
+      }
+    }
+    if !blockers.is_empty() {
+      return Err(blockers);
+    }
+    let mut order = self.install_order(targets);
+    order.retain(|id| removed.contains(id));
+    order.reverse();
+    Ok(order)
+  }
+
+  /// `Ok` when nothing depends on `unit` directly, else its direct dependents
+  /// in `DepId` order.
+  pub fn can_remove(&self, unit: DepId) -> Result<(), Vec<DepId>> {
+    let blockers = self.dependents_of(unit);
+    if blockers.is_empty() {
+      Ok(())
+    } else {
+      Err(blockers)
+    }
+  }
+
+  /// Units that `removed` or `explicitly_kept` reach now but that no kept
+  /// unit would reach once `removed` is gone, i.e. what an apt-style
+  /// autoremove offers to clean up. Paths through removed units don't count,
+  /// and the removed units themselves are not listed.
+  pub fn autoremove_set(&self, removed: &[DepId], explicitly_kept: &[DepId]) -> BTreeSet<DepId> {
+    let gone = removed.iter().copied().collect::<BTreeSet<_>>();
+    let mut kept = BTreeSet::new();
+    let mut stack = explicitly_kept.iter().copied().filter(|id| !gone.contains(id)).collect::<Vec<_>>();
+    while let Some(id) = stack.pop() {
+      if kept.insert(id) {
+        stack.extend(self.direct(id).iter().filter(|dep| !gone.contains(dep)));
+      }
+    }
+    let mut before = self.closure_of(removed);
+    before.extend(self.closure_of(explicitly_kept));
+    before.retain(|id| !gone.contains(id) && !kept.contains(id));
+    before
+  }
+}
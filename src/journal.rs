@@ -0,0 +1,196 @@
+use std::{
+  cell::RefCell,
+  fs::OpenOptions,
+  io::{self, Read, Seek, SeekFrom, Write},
+  path::{Path, PathBuf},
+  rc::Rc,
+};
+
+use crate::{ChangeEvent, DepId, DepTreeBuilder};
+
+const MAGIC: &[u8; 8] = b"DEPTLOG\0";
+
+const UNIT_ADDED: u8 = 0;
+const UNIT_REMOVED: u8 = 1;
+const EDGE_ADDED: u8 = 2;
+const EDGE_REMOVED: u8 = 3;
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/// One record: a tag byte followed by one or two units as little-endian
+/// `u64` pairs.
+fn encode(event: &ChangeEvent) -> Vec<u8> {
+  let (tag, units) = match *event {
+    ChangeEvent::UnitAdded(id) => (UNIT_ADDED, vec![id]),
+    ChangeEvent::UnitRemoved(id) => (UNIT_REMOVED, vec![id]),
+    ChangeEvent::EdgeAdded(id, dep) => (EDGE_ADDED, vec![id, dep]),
+    ChangeEvent::EdgeRemoved(id, dep) => (EDGE_REMOVED, vec![id, dep]),
+  };
+  let mut record = vec![tag];
+  for (id, version) in units {
+    record.extend(id.to_le_bytes());
+    record.extend((version as u64).to_le_bytes());
+  }
+  record
+}
+
+/// Decodes the record at the start of `bytes`, with its length. `None` if
+/// `bytes` ends part way through it.
+fn decode(bytes: &[u8]) -> io::Result<Option<(ChangeEvent, usize)>> {
+  let len = match bytes[0] {
+    UNIT_ADDED | UNIT_REMOVED => 17,
+    EDGE_ADDED | EDGE_REMOVED => 33,
+    tag => return Err(invalid(&format!("unknown delta log record {tag}"))),
+  };
+  if bytes.len() < len {
+    return Ok(None);
+  }
+  let unit = |offset: usize| -> DepId {
+    let id = u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    let version = u64::from_le_bytes(bytes[offset + 8..offset + 16].try_into().unwrap());
+    (id, version as usize)
+  };
+  let event = match bytes[0] {
+    UNIT_ADDED => ChangeEvent::UnitAdded(unit(1)),
+    UNIT_REMOVED => ChangeEvent::UnitRemoved(unit(1)),
+    EDGE_ADDED => ChangeEvent::EdgeAdded(unit(1), unit(17)),
+    _ => ChangeEvent::EdgeRemoved(unit(1), unit(17)),
+  };
+  Ok(Some((event, len)))
+}
+
+/// Handle on the append-only file behind a builder opened with
+/// `DepTreeBuilder::with_log`. Every change event, including the ones undone
+/// by a rolled back `Transaction`, is written as its own record, so replaying
+/// the file rebuilds the graph. Attributes and edge data are not logged.
+///
+/// Observers can't fail, so write errors are kept until `take_error` instead.
+#[derive(Clone, Debug)]
+pub struct DeltaLog {
+  path: PathBuf,
+  error: Rc<RefCell<Option<io::Error>>>,
+}
+
+impl DeltaLog {
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// First write error since the last call, if any. No records are written
+  /// while an error is pending.
+  pub fn take_error(&self) -> Option<io::Error> {
+    self.error.try_borrow_mut().unwrap().take()
+  }
+}
+
+impl DepTreeBuilder {
+  /// Opens the delta log at `path`, creating it if needed, and returns a
+  /// builder holding the graph it records, which appends every further change
+  /// to it. A record cut short by a crash is dropped from the end of the file.
+  pub fn with_log(path: impl AsRef<Path>) -> io::Result<(Self, DeltaLog)> {
+    let path = path.as_ref().to_path_buf();
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let mut builder = Self::new();
+    if bytes.is_empty() {
+      file.write_all(MAGIC)?;
+    } else if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+      return Err(invalid("not a dep-tree delta log"));
+    } else {
+      let mut offset = MAGIC.len();
+      while offset < bytes.len() {
+        let Some((event, len)) = decode(&bytes[offset..])? else {
+          break;
+        };
+        builder.replay(&event);
+        offset += len;
+      }
+      if offset < bytes.len() {
+        file.set_len(offset as u64)?;
+      }
+      file.seek(SeekFrom::Start(offset as u64))?;
+    }
+
+    let log = DeltaLog { path, error: Rc::default() };
+    let error = log.error.clone();
+    let file = RefCell::new(file);
+    builder.on_change(move |event: &ChangeEvent| {
+      let mut error = error.try_borrow_mut().unwrap();
+      if error.is_none() {
+        *error = file.try_borrow_mut().unwrap().write_all(&encode(event)).err();
+      }
+    });
+    Ok((builder, log))
+  }
+
+  fn replay(&mut self, event: &ChangeEvent) {
+    match *event {
+      ChangeEvent::UnitAdded(id) => self.with_dep(id, Vec::new()),
+      ChangeEvent::UnitRemoved(id) => self.without_unit(id),
+      ChangeEvent::EdgeAdded(id, dep) => self.with_dep(id, vec![dep]),
+      ChangeEvent::EdgeRemoved(id, dep) => self.without_dep(id, dep),
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dep-tree-{name}-{}.log", std::process::id()))
+  }
+
+  #[test]
+  fn records_round_trip() {
+    for event in [
+      ChangeEvent::UnitAdded((1, 2)),
+      ChangeEvent::UnitRemoved((u64::MAX, 0)),
+      ChangeEvent::EdgeAdded((1, 0), (2, 3)),
+      ChangeEvent::EdgeRemoved((4, 0), (5, 6)),
+    ] {
+      let record = encode(&event);
+      assert_eq!(decode(&record).unwrap(), Some((event, record.len())));
+      assert_eq!(decode(&record[..record.len() - 1]).unwrap(), None);
+    }
+    assert_eq!(decode(&[9]).unwrap_err().to_string(), "unknown delta log record 9");
+  }
+
+  #[test]
+  fn reopening_replays_the_log() {
+    let path = temp_path("replay");
+    let _ = std::fs::remove_file(&path);
+    {
+      let (mut builder, log) = DepTreeBuilder::with_log(&path).unwrap();
+      builder.with_dep((1, 0), vec![(2, 0), (3, 0)]);
+      builder.with_dep((2, 0), vec![(3, 0)]);
+      builder.without_dep((1, 0), (3, 0));
+      builder.with_dep((4, 0), Vec::new());
+      builder.without_unit((4, 0));
+      assert!(log.take_error().is_none());
+      assert_eq!(log.path(), path);
+    }
+    let len = std::fs::metadata(&path).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&encode(&ChangeEvent::EdgeAdded((2, 0), (1, 0)))[..20]).unwrap();
+    drop(file);
+
+    let (builder, _) = DepTreeBuilder::with_log(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    let tree = builder.build().unwrap();
+    let deps = tree.inner.iter().map(|(&id, deps)| (id, deps.to_vec())).collect::<Vec<_>>();
+    assert_eq!(deps, [((1, 0), vec![(2, 0)]), ((2, 0), vec![(3, 0)])]);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn rejects_other_files() {
+    let path = temp_path("foreign");
+    std::fs::write(&path, b"not a log").unwrap();
+    assert_eq!(DepTreeBuilder::with_log(&path).unwrap_err().to_string(), "not a dep-tree delta log");
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,409 @@
+use std::fmt::{self, Display, Write};
+
+use crate::AttrValue;
+
+/// Just enough JSON for the exporters and importers.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+  Null,
+  Bool(bool),
+  Integer(u64),
+  Number(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+  pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Json)>) -> Self {
+    Self::Object(fields.into_iter().map(|(key, value)| (key.into(), value)).collect())
+  }
+
+  /// Parses a single JSON document, failing with the byte offset of the
+  /// first error.
+  pub fn parse(text: &str) -> Result<Self, (usize, String)> {
+    let mut parser = Parser { text, position: 0, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+      return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+  }
+
+  pub fn get(&self, key: &str) -> Option<&Json> {
+    match self {
+      Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Self::String(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_u64(&self) -> Option<u64> {
+    match *self {
+      Self::Integer(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_array(&self) -> Option<&[Json]> {
+    match self {
+      Self::Array(items) => Some(items),
+      _ => None,
+    }
+  }
+
+  /// Inverse of `From<&AttrValue>`, `None` for values no attribute maps to.
+  pub fn as_attr(&self) -> Option<AttrValue> {
+    match *self {
+      Self::Bool(value) => Some(AttrValue::Bool(value)),
+      Self::Integer(value) => i64::try_from(value).ok().map(AttrValue::Int),
+      Self::Number(value) if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 => {
+        Some(AttrValue::Int(value as i64))
+      }
+      Self::String(ref value) => Some(AttrValue::from(value.as_str())),
+      _ => None,
+    }
+  }
+}
+
+/// Deepest nesting of arrays and objects `Json::parse` accepts.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+  text: &'a str,
+  position: usize,
+  depth: usize,
+}
+
+impl Parser<'_> {
+  fn error(&self, message: &str) -> (usize, String) {
+    (self.position, message.to_owned())
+  }
+
+  fn peek(&self) -> Option<u8> {
+    self.text.as_bytes().get(self.position).copied()
+  }
+
+  fn skip_whitespace(&mut self) {
+    while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+      self.position += 1;
+    }
+  }
+
+  fn expect(&mut self, byte: u8) -> Result<(), (usize, String)> {
+    self.skip_whitespace();
+    if self.peek() != Some(byte) {
+      return Err(self.error(&format!("expected `{}`", byte as char)));
+    }
+    self.position += 1;
+    Ok(())
+  }
+
+  fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, (usize, String)> {
+    if !self.text[self.position..].starts_with(keyword) {
+      return Err(self.error("unexpected token"));
+    }
+    self.position += keyword.len();
+    Ok(value)
+  }
+
+  fn value(&mut self) -> Result<Json, (usize, String)> {
+    self.skip_whitespace();
+    match self.peek() {
+      Some(b'n') => self.keyword("null", Json::Null),
+      Some(b't') => self.keyword("true", Json::Bool(true)),
+      Some(b'f') => self.keyword("false", Json::Bool(false)),
+      Some(b'"') => self.string().map(Json::String),
+      Some(b'[') => self.nested(Self::array),
+      Some(b'{') => self.nested(Self::object),
+      Some(b'-' | b'0'..=b'9') => self.number(),
+      _ => Err(self.error("unexpected token")),
+    }
+  }
+
+  /// Parses a nested array or object, refusing to go deeper than
+  /// `MAX_DEPTH` so hostile input can't exhaust the stack.
+  fn nested(&mut self, parse: fn(&mut Self) -> Result<Json, (usize, String)>) -> Result<Json, (usize, String)> {
+    if self.depth == MAX_DEPTH {
+      return Err(self.error("nesting too deep"));
+    }
+    self.depth += 1;
+    let value = parse(self);
+    self.depth -= 1;
+    value
+  }
+
+  fn array(&mut self) -> Result<Json, (usize, String)> {
+    self.position += 1;
+    let mut items = Vec::new();
+    self.skip_whitespace();
+    if self.peek() == Some(b']') {
+      self.position += 1;
+      return Ok(Json::Array(items));
+    }
+    loop {
+      items.push(self.value()?);
+      self.skip_whitespace();
+      match self.peek() {
+        Some(b',') => self.position += 1,
+        Some(b']') => {
+          self.position += 1;
+          return Ok(Json::Array(items));
+        }
+        _ => return Err(self.error("expected `,` or `]`")),
+      }
+    }
+  }
+
+  fn object(&mut self) -> Result<Json, (usize, String)> {
+    self.position += 1;
+    let mut fields = Vec::new();
+    self.skip_whitespace();
+    if self.peek() == Some(b'}') {
+      self.position += 1;
+      return Ok(Json::Object(fields));
+    }
+    loop {
+      self.skip_whitespace();
+      if self.peek() != Some(b'"') {
+        return Err(self.error("expected a key"));
+      }
+      let key = self.string()?;
+      self.expect(b':')?;
+      fields.push((key, self.value()?));
+      self.skip_whitespace();
+      match self.peek() {
+        Some(b',') => self.position += 1,
+        Some(b'}') => {
+          self.position += 1;
+          return Ok(Json::Object(fields));
+        }
+        _ => return Err(self.error("expected `,` or `}`")),
+      }
+    }
+  }
+
+  fn number(&mut self) -> Result<Json, (usize, String)> {
+    let start = self.position;
+    while matches!(self.peek(), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+      self.position += 1;
+    }
+    let literal = &self.text[start..self.position];
+    if let Ok(value) = literal.parse::<u64>() {
+      return Ok(Json::Integer(value));
+    }
+    literal
+      .parse::<f64>()
+      .map(Json::Number)
+      .map_err(|_| (start, format!("invalid number `{literal}`")))
+  }
+
+  fn hex4(&mut self) -> Result<u32, (usize, String)> {
+    let code = self
+      .text
+      .get(self.position..self.position + 4)
+      .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+      .ok_or_else(|| self.error("invalid unicode escape"))?;
+    self.position += 4;
+    Ok(code)
+  }
+
+  fn string(&mut self) -> Result<String, (usize, String)> {
+    self.position += 1;
+    let mut value = String::new();
+    loop {
+      let rest = &self.text[self.position..];
+      let Some(c) = rest.chars().next() else {
+        return Err(self.error("unterminated string"));
+      };
+      self.position += c.len_utf8();
+      match c {
+        '"' => return Ok(value),
+        '\\' => {
+          let Some(escape) = self.peek() else {
+            return Err(self.error("unterminated string"));
+          };
+          self.position += 1;
+          match escape {
+            b'"' => value.push('"'),
+            b'\\' => value.push('\\'),
+            b'/' => value.push('/'),
+            b'b' => value.push('\u{8}'),
+            b'f' => value.push('\u{c}'),
+            b'n' => value.push('\n'),
+            b'r' => value.push('\r'),
+            b't' => value.push('\t'),
+            b'u' => {
+              let mut code = self.hex4()?;
+              if (0xd800..0xdc00).contains(&code) && self.text[self.position..].starts_with("\\u") {
+                self.position += 2;
+                let low = self.hex4()?;
+                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+              }
+              value.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            _ => return Err(self.error("invalid escape")),
+          }
+        }
+        c => value.push(c),
+      }
+    }
+  }
+}
+
+impl From<&str> for Json {
+  fn from(value: &str) -> Self {
+    Self::String(value.to_owned())
+  }
+}
+
+impl From<String> for Json {
+  fn from(value: String) -> Self {
+    Self::String(value)
+  }
+}
+
+impl From<u64> for Json {
+  fn from(value: u64) -> Self {
+    Self::Integer(value)
+  }
+}
+
+impl From<usize> for Json {
+  fn from(value: usize) -> Self {
+    Self::Integer(value as u64)
+  }
+}
+
+impl From<&AttrValue> for Json {
+  fn from(value: &AttrValue) -> Self {
+    match *value {
+      AttrValue::String(ref value) => Self::from(value.as_str()),
+      AttrValue::Int(value) => match u64::try_from(value) {
+        Ok(value) => Self::Integer(value),
+        Err(_) => Self::Number(value as f64),
+      },
+      AttrValue::Bool(value) => Self::Bool(value),
+    }
+  }
+}
+
+impl Display for Json {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Null => f.write_str("null"),
+      Self::Bool(value) => write!(f, "{value}"),
+      Self::Integer(value) => write!(f, "{value}"),
+      Self::Number(value) => write!(f, "{value}"),
+      Self::String(value) => write_string(f, value),
+      Self::Array(items) => {
+        f.write_char('[')?;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write!(f, "{item}")?;
+        }
+        f.write_char(']')
+      }
+      Self::Object(fields) => {
+        f.write_char('{')?;
+        for (i, (key, value)) in fields.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write_string(f, key)?;
+          write!(f, ":{value}")?;
+        }
+        f.write_char('}')
+      }
+    }
+  }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+  f.write_char('"')?;
+  for c in value.chars() {
+    match c {
+      '"' => f.write_str("\\\"")?,
+      '\\' => f.write_str("\\\\")?,
+      '\n' => f.write_str("\\n")?,
+      '\r' => f.write_str("\\r")?,
+      '\t' => f.write_str("\\t")?,
+      c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+      c => f.write_char(c)?,
+    }
+  }
+  f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_every_kind_of_value() {
+    let json = Json::parse(r#" {"a": [1, -2, 2.5, true, false, null], "b": "x\"\u00e9\ud83d\ude00"} "#).unwrap();
+    let expected = Json::object([
+      (
+        "a",
+        Json::Array(vec![
+          Json::Integer(1),
+          Json::Number(-2.0),
+          Json::Number(2.5),
+          Json::Bool(true),
+          Json::Bool(false),
+          Json::Null,
+        ]),
+      ),
+      ("b", Json::from("x\"\u{e9}\u{1f600}")),
+    ]);
+    assert_eq!(json, expected);
+    assert_eq!(json.get("b").and_then(Json::as_str), Some("x\"\u{e9}\u{1f600}"));
+    assert_eq!(json.get("a").and_then(Json::as_array).map(<[Json]>::len), Some(6));
+  }
+
+  #[test]
+  fn display_round_trips() {
+    let json = Json::object([
+      ("text", Json::from("line\nbreak\t\u{1}")),
+      ("list", Json::Array(vec![Json::from(7u64), Json::Null, Json::object::<&str>([])])),
+    ]);
+    assert_eq!(json.to_string(), r#"{"text":"line\nbreak\t\u0001","list":[7,null,{}]}"#);
+    assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+  }
+
+  #[test]
+  fn reports_error_offsets() {
+    assert_eq!(Json::parse("[1, 2").unwrap_err(), (5, "expected `,` or `]`".to_owned()));
+    assert_eq!(Json::parse("{1: 2}").unwrap_err(), (1, "expected a key".to_owned()));
+    assert_eq!(Json::parse("\"abc").unwrap_err().1, "unterminated string");
+    assert_eq!(Json::parse("\"\\q\"").unwrap_err().1, "invalid escape");
+    assert_eq!(Json::parse("1 2").unwrap_err(), (2, "trailing characters".to_owned()));
+    assert_eq!(Json::parse("1.2.3").unwrap_err(), (0, "invalid number `1.2.3`".to_owned()));
+  }
+
+  #[test]
+  fn rejects_deep_nesting() {
+    let deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+    assert_eq!(Json::parse(&deep).unwrap_err().1, "nesting too deep");
+    let shallow = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+    assert!(Json::parse(&shallow).is_ok());
+  }
+
+  #[test]
+  fn attr_values_round_trip() {
+    for value in [AttrValue::Int(-3), AttrValue::Int(4), AttrValue::Bool(true), AttrValue::from("s")] {
+      assert_eq!(Json::from(&value).as_attr(), Some(value));
+    }
+    assert_eq!(Json::Number(0.5).as_attr(), None);
+    assert_eq!(Json::Null.as_attr(), None);
+  }
+}
@@ -1,25 +1,492 @@
 use std::{
-  cell::RefCell,
-  collections::{btree_map::Entry, BTreeMap, BTreeSet},
+  any::Any,
+  cell::{Cell, RefCell},
+  cmp::Ordering,
+  collections::{btree_map::Entry, BTreeMap, BTreeSet, VecDeque},
   rc::Rc,
+  sync::mpsc,
 };
 use thiserror::Error;
 
+use json::Json;
+use rules::Rules;
+
+mod analysis;
+mod critical;
+pub mod export;
+mod history;
+pub mod import;
+mod install;
+mod journal;
+mod json;
+mod lockfile;
+mod mapped;
+mod matrix;
+mod query;
+mod render;
+mod resolver;
+mod rules;
+mod runner;
+mod scheduler;
+mod simulation;
+mod solver;
+mod stream;
+mod target;
+mod xml;
+
+pub use analysis::{ChurnWeights, DedupSuggestion, Diamond, FeedbackEdge, RankContext, RedundantEdge};
+pub use critical::{CriticalPath, Progress, Speedup, SpeedupAnalysis, TreeMetrics, UnitTiming};
+pub use history::{History, Revision};
+pub use install::VendorSet;
+pub use journal::DeltaLog;
+pub use mapped::MappedTree;
+pub use matrix::{AdjacencyMatrix, Reachability};
+pub use query::{Query, QueryError};
+pub use resolver::Resolver;
+pub use rules::BuildReport;
+pub use runner::{Executor, RunReport, ThreadExecutor};
+pub use scheduler::{CancelToken, Cancellation, ReadyIter, Scheduler, SchedulerError, SchedulerResult};
+pub use simulation::{ScheduledUnit, Simulation};
+pub use solver::{Registry, Requirement, SolveError};
+pub use stream::{AdjacencyStore, FileStore, MemoryStore, StreamError, StreamResult, StreamingBuilder};
+pub use target::TargetExpr;
+
 pub type DepId = (u64, usize);
 
+/// Attribute recording `DepTreeBuilder::pin`.
+const PINNED: &str = "pinned";
+
+type DepMap = BTreeMap<DepId, Vec<DepId>>;
+type SharedDepMap = BTreeMap<DepId, Rc<[DepId]>>;
+type Edge = (DepId, DepId);
+type Attrs = BTreeMap<DepId, BTreeMap<String, AttrValue>>;
+
+fn label((id, version): DepId) -> String {
+  format!("({id}, {version})")
+}
+
+fn cycle_message(cycle: &[DepId]) -> String {
+  match (cycle.first(), cycle.last()) {
+    (Some(first), Some(last)) => {
+      let path = cycle.iter().map(|&unit| label(unit)).collect::<Vec<_>>().join(" -> ");
+      format!("unit `{first:?}` recurses when depending on `{last:?}`, `{path}`")
+    }
+    _ => "circular dependency through no units".to_owned(),
+  }
+}
+
+/// Inverse of `label`, tolerating missing whitespace.
+fn parse_label(text: &str) -> Option<DepId> {
+  let (id, version) = text.trim().strip_prefix('(')?.strip_suffix(')')?.split_once(',')?;
+  Some((id.trim().parse().ok()?, version.trim().parse().ok()?))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+  Dependencies,
+  Dependents,
+  Both,
+}
+
+/// Value of a node attribute, see `DepTreeBuilder::set_attr`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AttrValue {
+  String(String),
+  Int(i64),
+  Bool(bool),
+}
+
+impl AttrValue {
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Self::String(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_int(&self) -> Option<i64> {
+    match *self {
+      Self::Int(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match *self {
+      Self::Bool(value) => Some(value),
+      _ => None,
+    }
+  }
+}
+
+impl From<&str> for AttrValue {
+  fn from(value: &str) -> Self {
+    Self::String(value.to_owned())
+  }
+}
+
+impl From<String> for AttrValue {
+  fn from(value: String) -> Self {
+    Self::String(value)
+  }
+}
+
+impl From<i64> for AttrValue {
+  fn from(value: i64) -> Self {
+    Self::Int(value)
+  }
+}
+
+impl From<bool> for AttrValue {
+  fn from(value: bool) -> Self {
+    Self::Bool(value)
+  }
+}
+
+impl std::fmt::Display for AttrValue {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::String(value) => f.write_str(value),
+      Self::Int(value) => write!(f, "{value}"),
+      Self::Bool(value) => write!(f, "{value}"),
+    }
+  }
+}
+
+/// What `DepTree::rank` counts per unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Metric {
+  /// Distinct transitive dependencies of every declared unit.
+  Dependencies,
+  /// Direct dependents of every unit.
+  Dependents,
+  /// Distinct transitive dependents of every unit.
+  TransitiveDependents,
+}
+
+fn most(a: &(DepId, usize), b: &(DepId, usize)) -> Ordering {
+  b.1.cmp(&a.1).then(a.0.cmp(&b.0))
+}
+
+fn least(a: &(DepId, usize), b: &(DepId, usize)) -> Ordering {
+  a.1.cmp(&b.1).then(a.0.cmp(&b.0))
+}
+
+/// Order of each unit's dependency list in a built `DepTree`. Units
+/// themselves are always ordered by `DepId`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DepOrder {
+  /// The order the edges were added in, across repeated `with_dep` calls.
+  #[default]
+  Insertion,
+  /// Ascending by `DepId`.
+  Sorted,
+}
+
+/// Breadth-first hop counts of everything reachable from `start` through
+/// `next`, excluding `start` itself, stopping `max_depth` hops out.
+fn bfs<I: IntoIterator<Item = DepId>>(
+  start: DepId,
+  max_depth: Option<usize>,
+  next: impl Fn(DepId) -> I,
+) -> BTreeMap<DepId, usize> {
+  let mut reached = BTreeMap::new();
+  let mut queue = VecDeque::from([(start, 0)]);
+  while let Some((id, depth)) = queue.pop_front() {
+    if max_depth.is_some_and(|max| depth >= max) {
+      continue;
+    }
+    for next in next(id) {
+      if next != start && !reached.contains_key(&next) {
+        reached.insert(next, depth + 1);
+        queue.push_back((next, depth + 1));
+      }
+    }
+  }
+  reached
+}
+
+/// Number of distinct units reachable from every unit of the acyclic `graph`,
+/// itself excluded. Closures are unioned as bitsets in one pass over a
+/// topological order, a block of target units at a time to bound memory.
+fn closure_sizes<V: AsRef<[DepId]>>(graph: &BTreeMap<DepId, V>) -> BTreeMap<DepId, usize> {
+  const MAX_WORDS: usize = 1 << 23;
+
+  let units = graph
+    .iter()
+    .flat_map(|(&id, next)| std::iter::once(id).chain(next.as_ref().iter().copied()))
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+    .collect::<Vec<_>>();
+  let index = |id: &DepId| units.binary_search(id).unwrap();
+  let next = units
+    .iter()
+    .map(|id| graph.get(id).map_or(&[][..], AsRef::as_ref).iter().map(index).collect::<Vec<_>>())
+    .collect::<Vec<_>>();
+
+  // Iterative post-order, so every unit comes after everything it reaches.
+  let mut order = Vec::with_capacity(units.len());
+  let mut visited = vec![false; units.len()];
+  for root in 0..units.len() {
+    if visited[root] {
+      continue;
+    }
+    visited[root] = true;
+    let mut stack = vec![(root, 0)];
+    while let Some((unit, child)) = stack.last_mut() {
+      match next[*unit].get(*child) {
+        Some(&dep) => {
+          *child += 1;
+          if !visited[dep] {
+            visited[dep] = true;
+            stack.push((dep, 0));
+          }
+        }
+        None => {
+          order.push(*unit);
+          stack.pop();
+        }
+      }
+    }
+  }
+
+  let n = units.len();
+  let total_words = n.div_ceil(64);
+  let words = (MAX_WORDS / n.max(1)).clamp(1, total_words.max(1));
+  let mut sizes = vec![0; n];
+  let mut bits = vec![0u64; n * words];
+  let mut row = vec![0u64; words];
+  for block in (0..total_words).step_by(words) {
+    let first = block * 64;
+    let last = (first + words * 64).min(n);
+    bits.fill(0);
+    for &unit in &order {
+      row.fill(0);
+      for &dep in &next[unit] {
+        for (word, &dep_word) in row.iter_mut().zip(&bits[dep * words..(dep + 1) * words]) {
+          *word |= dep_word;
+        }
+        if (first..last).contains(&dep) {
+          row[(dep - first) / 64] |= 1 << ((dep - first) % 64);
+        }
+      }
+      sizes[unit] += row.iter().map(|word| word.count_ones() as usize).sum::<usize>();
+      bits[unit * words..(unit + 1) * words].copy_from_slice(&row);
+    }
+  }
+  units.into_iter().zip(sizes).collect()
+}
+
 #[derive(Clone, Debug, Error)]
 pub enum DepTreeBuilderError {
   #[error("unit `{0:?}` depends on itself")]
   SelfDependency(DepId),
-  #[error("unit `{0:?}` recurses when depending on `{1:?}`, `{2}`")]
-  CircularDependency(DepId, DepId, String),
+  /// Units of the cycle in path order, from the unit that recursed to the
+  /// dependency that closed it.
+  #[error("{}", cycle_message(.0))]
+  CircularDependency(Vec<DepId>),
+  #[error("unit `{0:?}` already exists")]
+  DuplicateUnit(DepId),
+  #[error("unit `{0:?}` does not exist")]
+  UnknownUnit(DepId),
+  #[error("unit `{0:?}` is pinned")]
+  Pinned(DepId),
+  #[error("virtual unit `{0:?}` has several providers and none is preferred: {1:?}")]
+  AmbiguousProvider(DepId, Vec<DepId>),
+  /// Every violation found in one pass, when there was more than one.
+  #[error("{} problems: {}", .0.len(), .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+  Multiple(Vec<DepTreeBuilderError>),
+}
+
+impl DepTreeBuilderError {
+  fn circular(stack: &[DepId]) -> Self {
+    Self::CircularDependency(stack.to_vec())
+  }
+
+  /// Stable identifier of the variant, meant for tools matching on errors.
+  pub fn code(&self) -> &'static str {
+    match self {
+      Self::SelfDependency(..) => "self_dependency",
+      Self::CircularDependency(..) => "circular_dependency",
+      Self::DuplicateUnit(..) => "duplicate_unit",
+      Self::UnknownUnit(..) => "unknown_unit",
+      Self::Pinned(..) => "pinned",
+      Self::AmbiguousProvider(..) => "ambiguous_provider",
+      Self::Multiple(..) => "multiple",
+    }
+  }
+
+  /// Units involved, in cycle order for `CircularDependency`.
+  pub fn units(&self) -> Vec<DepId> {
+    match self {
+      Self::SelfDependency(unit) | Self::DuplicateUnit(unit) | Self::UnknownUnit(unit) | Self::Pinned(unit) => {
+        vec![*unit]
+      }
+      Self::CircularDependency(cycle) => cycle.clone(),
+      Self::AmbiguousProvider(unit, providers) => std::iter::once(*unit).chain(providers.iter().copied()).collect(),
+      Self::Multiple(errors) => errors.iter().flat_map(Self::units).collect(),
+    }
+  }
+
+  /// Machine-readable form: `code`, `message`, `units` as `[id, version]`
+  /// pairs and, for `Multiple`, the nested `errors`.
+  pub fn to_json(&self) -> String {
+    self.json().to_string()
+  }
+
+  fn json(&self) -> Json {
+    let units = self
+      .units()
+      .into_iter()
+      .map(|(id, version)| Json::Array(vec![Json::from(id), Json::from(version)]))
+      .collect();
+    let mut fields = vec![
+      ("code", Json::from(self.code())),
+      ("message", Json::from(self.to_string())),
+      ("units", Json::Array(units)),
+    ];
+    if let Self::Multiple(errors) = self {
+      fields.push(("errors", Json::Array(errors.iter().map(Self::json).collect())));
+    }
+    Json::object(fields)
+  }
 }
 
 pub type DepTreeBuilderResult<T> = Result<T, DepTreeBuilderError>;
 
+/// Builds a tree from `unit => [deps]` pairs and evaluates to the result of
+/// `DepTreeBuilder::build`, e.g. `dep_tree! { (1, 0) => [(2, 0)], (2, 0) => [] }?`.
+#[macro_export]
+macro_rules! dep_tree {
+  ($($unit:expr => [$($dep:expr),* $(,)?]),* $(,)?) => {
+    $crate::DepTreeBuilder::new()
+      $(.with_dep($unit, ::std::vec![$($dep),*]))*
+      .build()
+  };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeEvent {
+  UnitAdded(DepId),
+  UnitRemoved(DepId),
+  EdgeAdded(DepId, DepId),
+  EdgeRemoved(DepId, DepId),
+}
+
+impl ChangeEvent {
+  /// Events that turn `from` into `to`, removals first.
+  fn diff(from: &DepMap, to: &DepMap) -> Vec<Self> {
+    let mut events = Vec::new();
+    for (&unit, deps) in from.iter() {
+      let kept = to.get(&unit);
+      for &dep in deps {
+        if !kept.is_some_and(|kept| kept.contains(&dep)) {
+          events.push(Self::EdgeRemoved(unit, dep));
+        }
+      }
+      if kept.is_none() {
+        events.push(Self::UnitRemoved(unit));
+      }
+    }
+    for (&unit, deps) in to.iter() {
+      let existing = from.get(&unit);
+      if existing.is_none() {
+        events.push(Self::UnitAdded(unit));
+      }
+      for &dep in deps {
+        if !existing.is_some_and(|existing| existing.contains(&dep)) {
+          events.push(Self::EdgeAdded(unit, dep));
+        }
+      }
+    }
+    events
+  }
+}
+
+type Observer = Box<dyn Fn(&ChangeEvent)>;
+
+/// Domain type that knows its own unit and dependencies, see
+/// `DepTreeBuilder::add`.
+pub trait Dependable {
+  fn dep_id(&self) -> DepId;
+  fn deps(&self) -> Vec<DepId>;
+}
+
+/// Derives `Dependable` from `#[dep_id]` and `#[deps]` field attributes.
+#[cfg(feature = "derive")]
+pub use dep_tree_derive::Dependable;
+
+#[derive(Default)]
+struct Observers(Vec<Observer>);
+
+impl std::fmt::Debug for Observers {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Observers({})", self.0.len())
+  }
+}
+
+/// Per-edge side data, kept in step with the adjacency lists it describes.
+#[derive(Clone, Debug, Default)]
+struct EdgeData {
+  /// Declaration counts of edges declared more than once.
+  counts: BTreeMap<Edge, usize>,
+  payloads: BTreeMap<Edge, Rc<dyn Any>>,
+  /// Groups of edges that are only active in some of the effective trees.
+  groups: BTreeMap<Edge, BTreeSet<String>>,
+  /// Platforms conditional edges apply to.
+  targets: BTreeMap<Edge, Vec<TargetExpr>>,
+}
+
+impl EdgeData {
+  fn remove_edge(&mut self, edge: Edge) {
+    self.counts.remove(&edge);
+    self.payloads.remove(&edge);
+    self.groups.remove(&edge);
+    self.targets.remove(&edge);
+  }
+
+  fn remove_unit(&mut self, id: DepId) {
+    let keep = |&(unit, dep): &Edge| unit != id && dep != id;
+    self.counts.retain(|edge, _| keep(edge));
+    self.payloads.retain(|edge, _| keep(edge));
+    self.groups.retain(|edge, _| keep(edge));
+    self.targets.retain(|edge, _| keep(edge));
+  }
+
+  /// Copy without the entries for edges `inner` no longer has, `None` when
+  /// there are none to drop.
+  fn retain_present(&self, inner: &SharedDepMap) -> Option<Self> {
+    let present = |&(id, dep): &Edge| inner.get(&id).is_some_and(|deps| deps.contains(&dep));
+    let mut edges = self.counts.keys().chain(self.payloads.keys()).chain(self.groups.keys()).chain(self.targets.keys());
+    if edges.all(present) {
+      return None;
+    }
+    Some(Self {
+      counts: self.counts.iter().filter(|(edge, _)| present(edge)).map(|(&edge, &count)| (edge, count)).collect(),
+      payloads: self.payloads.iter().filter(|(edge, _)| present(edge)).map(|(&edge, payload)| (edge, payload.clone())).collect(),
+      groups: self.groups.iter().filter(|(edge, _)| present(edge)).map(|(&edge, groups)| (edge, groups.clone())).collect(),
+      targets: self.targets.iter().filter(|(edge, _)| present(edge)).map(|(&edge, exprs)| (edge, exprs.clone())).collect(),
+    })
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Options {
+  dedupe: bool,
+  multiplicity: bool,
+  all_cycles: bool,
+  order: DepOrder,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct DepTreeBuilder {
-  inner: Rc<RefCell<Box<BTreeMap<DepId, Vec<DepId>>>>>,
+  inner: Rc<RefCell<Box<DepMap>>>,
+  observers: Rc<RefCell<Observers>>,
+  options: Rc<Cell<Options>>,
+  duplicates: Rc<Cell<usize>>,
+  edges: Rc<RefCell<EdgeData>>,
+  attrs: Rc<RefCell<Attrs>>,
+  rules: Rc<RefCell<Rules>>,
 }
 
 impl DepTreeBuilder {
@@ -27,53 +494,279 @@ impl DepTreeBuilder {
     Self::default()
   }
 
+  /// Registers `observer` to be called after every change to the graph,
+  /// including the ones undone by a rolled back `Transaction`.
+  pub fn on_change(&mut self, observer: impl Fn(&ChangeEvent) + 'static) -> Self {
+    self.observers.try_borrow_mut().unwrap().0.push(Box::new(observer));
+    self.clone()
+  }
+
+  /// Channel receiving every later change event, see `on_change`. Events sent
+  /// after the receiver is dropped are discarded.
+  pub fn changes(&mut self) -> mpsc::Receiver<ChangeEvent> {
+    let (sender, receiver) = mpsc::channel();
+    self.on_change(move |event| {
+      let _ = sender.send(event.clone());
+    });
+    receiver
+  }
+
+  /// Drops dependencies already listed for a unit instead of adding them a
+  /// second time. Off by default.
+  pub fn dedupe_edges(&mut self, dedupe: bool) -> Self {
+    let mut options = self.options.get();
+    options.dedupe = dedupe;
+    self.options.set(options);
+    self.clone()
+  }
+
+  /// Stores a dependency declared several times for the same unit once and
+  /// remembers how often it was declared, see `DepTree::edge_count`. Off by
+  /// default.
+  pub fn track_multiplicity(&mut self, track: bool) -> Self {
+    let mut options = self.options.get();
+    options.multiplicity = track;
+    self.options.set(options);
+    self.clone()
+  }
+
+  /// Makes `build` and `Transaction::commit` keep going after the first cycle
+  /// and report each further one found. Self-dependencies are always reported
+  /// together. The list is not exhaustive: units on a reported cycle are not
+  /// searched again, so other cycles through them are left out. Use
+  /// `cycles` to list every elementary cycle.
+  pub fn report_all_cycles(&mut self, all: bool) -> Self {
+    let mut options = self.options.get();
+    options.all_cycles = all;
+    self.options.set(options);
+    self.clone()
+  }
+
+  /// Sets how `build` orders each unit's dependencies, see `DepOrder`.
+  pub fn dep_order(&mut self, order: DepOrder) -> Self {
+    let mut options = self.options.get();
+    options.order = order;
+    self.options.set(options);
+    self.clone()
+  }
+
+  /// Repeated edges passed to `with_dep` so far, whether they were dropped by
+  /// `dedupe_edges` or kept.
+  pub fn duplicate_edges(&self) -> usize {
+    self.duplicates.get()
+  }
+
   pub fn with_dep(&mut self, id: DepId, deps: Vec<DepId>) -> Self {
+    let mut events = Vec::new();
     let mut inner_lock = self.inner.try_borrow_mut().unwrap();
-    match inner_lock.entry(id) {
-      Entry::Vacant(entry) => {
-        entry.insert(deps);
+    if !inner_lock.contains_key(&id) {
+      events.push(ChangeEvent::UnitAdded(id));
+    }
+    let list = inner_lock.entry(id).or_default();
+    let options = self.options.get();
+    for dep in deps {
+      if list.contains(&dep) {
+        self.duplicates.set(self.duplicates.get() + 1);
+        if options.multiplicity {
+          *self.edges.try_borrow_mut().unwrap().counts.entry((id, dep)).or_insert(1) += 1;
+          continue;
+        }
+        if options.dedupe {
+          continue;
+        }
       }
-      Entry::Occupied(mut entry) => {
-        entry.get_mut().extend(deps);
+      list.push(dep);
+      events.push(ChangeEvent::EdgeAdded(id, dep));
+    }
+    drop(inner_lock);
+    self.notify(&events);
+    self.clone()
+  }
+
+  /// Registers `item` with its dependencies, like `with_dep`.
+  pub fn add(&mut self, item: &impl Dependable) -> Self {
+    self.with_dep(item.dep_id(), item.deps())
+  }
+
+  pub fn add_all<'a, D: Dependable + 'a>(&mut self, items: impl IntoIterator<Item = &'a D>) -> Self {
+    for item in items {
+      self.add(item);
+    }
+    self.clone()
+  }
+
+  /// Sets attribute `key` of `id`, replacing any earlier value. Attributes
+  /// carry over into the built tree and its exports.
+  pub fn set_attr(&mut self, id: DepId, key: impl Into<String>, value: impl Into<AttrValue>) -> Self {
+    self.attrs.try_borrow_mut().unwrap().entry(id).or_default().insert(key.into(), value.into());
+    self.clone()
+  }
+
+  /// Holds `id` in place: tree passes such as `DepTree::contract`,
+  /// `replace_id`, `split` and `prune_unreachable` refuse to rewrite or drop
+  /// it, `build` fails if `with_override` would redirect edges away from it,
+  /// and `dedup_suggestions` leaves its edges alone. Stored as the
+  /// `pinned` attribute, so it survives exports that keep attributes.
+  pub fn pin(&mut self, id: DepId) -> Self {
+    self.set_attr(id, PINNED, true)
+  }
+
+  /// Adds the `id -> dep` edge unless it is already there and attaches
+  /// `payload` to it, replacing any earlier one. Read it back with
+  /// `DepTree::edge`.
+  pub fn with_edge<E: Any>(&mut self, id: DepId, dep: DepId, payload: E) -> Self {
+    let exists = self.inner.try_borrow().unwrap().get(&id).is_some_and(|deps| deps.contains(&dep));
+    if !exists {
+      self.with_dep(id, vec![dep]);
+    }
+    self.edges.try_borrow_mut().unwrap().payloads.insert((id, dep), Rc::new(payload));
+    self.clone()
+  }
+
+  /// Adds the `id -> dep` edge unless it is already there and puts it in
+  /// `group`. Edges in groups only show up in the trees of
+  /// `DepTree::with_groups` that activate one of their groups; edges in no
+  /// group are always there.
+  pub fn in_group(&mut self, id: DepId, dep: DepId, group: impl Into<String>) -> Self {
+    let exists = self.inner.try_borrow().unwrap().get(&id).is_some_and(|deps| deps.contains(&dep));
+    if !exists {
+      self.with_dep(id, vec![dep]);
+    }
+    self.edges.try_borrow_mut().unwrap().groups.entry((id, dep)).or_default().insert(group.into());
+    self.clone()
+  }
+
+  /// Like `with_dep`, but rejects edges that would close a cycle right away
+  /// instead of deferring the error to `build`.
+  pub fn try_with_dep(&mut self, id: DepId, deps: Vec<DepId>) -> DepTreeBuilderResult<Self> {
+    Self::check_new_deps(id, &deps, &self.inner.try_borrow().unwrap())?;
+    Ok(self.with_dep(id, deps))
+  }
+
+  pub fn without_dep(&mut self, id: DepId, dep: DepId) -> Self {
+    let mut inner_lock = self.inner.try_borrow_mut().unwrap();
+    let removed = inner_lock.get_mut(&id).is_some_and(|deps| {
+      let len = deps.len();
+      deps.retain(|&d| d != dep);
+      deps.len() != len
+    });
+    drop(inner_lock);
+    if removed {
+      self.edges.try_borrow_mut().unwrap().remove_edge((id, dep));
+      self.notify(&[ChangeEvent::EdgeRemoved(id, dep)]);
+    }
+    self.clone()
+  }
+
+  /// Removes `id` together with every edge pointing at it.
+  pub fn without_unit(&mut self, id: DepId) -> Self {
+    let mut events = Vec::new();
+    let mut inner_lock = self.inner.try_borrow_mut().unwrap();
+    for (&unit, deps) in inner_lock.iter_mut() {
+      if deps.contains(&id) {
+        deps.retain(|&d| d != id);
+        events.push(ChangeEvent::EdgeRemoved(unit, id));
       }
     }
+    if let Some(deps) = inner_lock.remove(&id) {
+      events.extend(deps.into_iter().map(|dep| ChangeEvent::EdgeRemoved(id, dep)));
+      events.push(ChangeEvent::UnitRemoved(id));
+    }
+    drop(inner_lock);
+    self.edges.try_borrow_mut().unwrap().remove_unit(id);
+    self.attrs.try_borrow_mut().unwrap().remove(&id);
+    self.notify(&events);
     self.clone()
   }
-  
+
+  /// Starts a batch of edits that can be undone as a whole, see `Transaction`.
+  pub fn begin(&self) -> Transaction {
+    let snapshot = Snapshot {
+      inner: self.inner.try_borrow().unwrap().clone(),
+      observers: self.observers.try_borrow().unwrap().0.len(),
+      options: self.options.get(),
+      duplicates: self.duplicates.get(),
+      edges: self.edges.try_borrow().unwrap().clone(),
+      attrs: self.attrs.try_borrow().unwrap().clone(),
+      rules: self.rules.try_borrow().unwrap().clone(),
+    };
+    Transaction { builder: self.clone(), snapshot: Some(snapshot) }
+  }
+
   pub fn build(self) -> DepTreeBuilderResult<Box<DepTree>> {
-    let inner = self.inner.try_borrow().unwrap();
-    let (mut visited, mut resolved): (
-      Vec<DepId>,
-      BTreeMap<DepId, Vec<DepId>>,
-    ) = (
-      Vec::new(),
-      BTreeMap::new(),
-    );
-    for (unit, deps) in inner.clone().into_iter() {
-      if deps.contains(&unit) {
-        return Err(DepTreeBuilderError::SelfDependency(unit));
+    let declared = self.inner.try_borrow().unwrap();
+    let attrs = self.attrs.try_borrow().unwrap();
+    let pinned = |id: DepId| {
+      attrs.get(&id).and_then(|attrs| attrs.get(PINNED)).and_then(AttrValue::as_bool).unwrap_or(false)
+    };
+    let resolved = self.rules.try_borrow().unwrap().apply(&declared, &self.edges.try_borrow().unwrap(), pinned)?;
+    let (inner, edges, report) = match &resolved {
+      Some((inner, edges, report)) => (inner, edges.clone(), report.clone()),
+      None => (&**declared, self.edges.try_borrow().unwrap().clone(), BuildReport::default()),
+    };
+    Self::check(inner, self.options.get().all_cycles)?;
+    let mut tree = if self.options.get().order == DepOrder::Sorted {
+      let mut sorted = inner.clone();
+      sorted.values_mut().for_each(|deps| deps.sort());
+      DepTree::from_map(&sorted)
+    } else {
+      DepTree::from_map(inner)
+    };
+    tree.edges = Rc::new(edges);
+    tree.report = Rc::new(report);
+    tree.attrs = Rc::new(attrs.clone());
+    Ok(Box::new(tree))
+  }
+
+  fn notify(&self, events: &[ChangeEvent]) {
+    let observers = self.observers.try_borrow().unwrap();
+    for event in events {
+      for observer in observers.0.iter() {
+        observer(event);
       }
+    }
+  }
+
+  fn validate<V: AsRef<[DepId]>>(tree: &BTreeMap<DepId, V>) -> DepTreeBuilderResult<()> {
+    Self::check(tree, false)
+  }
+
+  /// Collects every self-dependency and the first cycle, or with `all_cycles`
+  /// one cycle per search that still finds one, into a single error. Each
+  /// cycle is reported as the shortest one through the edge that closed it
+  /// during the search.
+  fn check<V: AsRef<[DepId]>>(tree: &BTreeMap<DepId, V>, all_cycles: bool) -> DepTreeBuilderResult<()> {
+    let mut errors = tree
+      .iter()
+      .filter(|(unit, deps)| deps.as_ref().contains(unit))
+      .map(|(&unit, _)| DepTreeBuilderError::SelfDependency(unit))
+      .collect::<Vec<_>>();
+    let mut visited = Vec::new();
+    for &unit in tree.keys() {
       let mut stack = Vec::new();
-      if self.has_circular_dependency(unit, &inner, &mut visited, &mut stack) {
-        return Err(DepTreeBuilderError::CircularDependency(
-          *stack.first().unwrap(),
-          *stack.last().unwrap(),
-          stack
-            .iter()
-            .map(|(id, version)| format!("({id}, {version})"))
-            .collect::<Vec<_>>()
-            .join(" -> ")
-        ));
+      if Self::has_circular_dependency(unit, tree, &mut visited, &mut stack) {
+        let target = stack.pop().unwrap();
+        let dependent = *stack.last().unwrap();
+        let cycle = Self::shortest_path(target, dependent, tree).unwrap_or_else(|| stack.clone());
+        errors.push(DepTreeBuilderError::circular(&cycle));
+        if !all_cycles {
+          break;
+        }
+        visited.extend(stack);
       }
-      resolved.insert(unit, deps);
     }
-    Ok(Box::new(DepTree::new(Rc::new(resolved))))
+    match errors.len() {
+      0 => Ok(()),
+      1 => Err(errors.pop().unwrap()),
+      _ => Err(DepTreeBuilderError::Multiple(errors)),
+    }
   }
 
-  fn has_circular_dependency(
-    &self,
+  /// Depth-first search for a cycle. On success `stack` ends with the unit
+  /// the last edge returned to, right after the unit it was reached from.
+  fn has_circular_dependency<V: AsRef<[DepId]>>(
     unit: DepId,
-    tree: &BTreeMap<DepId, Vec<DepId>>,
+    tree: &BTreeMap<DepId, V>,
     visited: &mut Vec<DepId>,
     stack: &mut Vec<DepId>,
   ) -> bool {
@@ -81,12 +774,13 @@ impl DepTreeBuilder {
       return false;
     }
     if stack.contains(&unit) {
+      stack.push(unit);
       return true;
     }
     stack.push(unit);
-    if let Some(deps) = tree.get(&unit) {
-      for &dep in deps {
-        if self.has_circular_dependency(dep, tree, visited, stack) {
+    if let Some(deps) = tree.get(&unit).map(AsRef::as_ref) {
+      for &dep in deps.iter().filter(|&&dep| dep != unit) {
+        if Self::has_circular_dependency(dep, tree, visited, stack) {
           return true;
         }
       }
@@ -95,48 +789,566 @@ impl DepTreeBuilder {
     visited.push(unit);
     false
   }
+
+  fn check_new_deps<V: AsRef<[DepId]>>(
+    id: DepId,
+    deps: &[DepId],
+    tree: &BTreeMap<DepId, V>,
+  ) -> DepTreeBuilderResult<()> {
+    if deps.contains(&id) {
+      return Err(DepTreeBuilderError::SelfDependency(id));
+    }
+    for &dep in deps {
+      if let Some(mut path) = Self::shortest_path(dep, id, tree) {
+        path.pop();
+        let mut stack = vec![id];
+        stack.extend(path);
+        return Err(DepTreeBuilderError::circular(&stack));
+      }
+    }
+    Ok(())
+  }
+
+  /// Fewest edges leading from `from` to `to`, both included.
+  fn shortest_path<V: AsRef<[DepId]>>(from: DepId, to: DepId, tree: &BTreeMap<DepId, V>) -> Option<Vec<DepId>> {
+    let mut parent = BTreeMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(id) = queue.pop_front() {
+      if id == to {
+        let mut path = vec![to];
+        while *path.last().unwrap() != from {
+          path.push(parent[path.last().unwrap()]);
+        }
+        path.reverse();
+        return Some(path);
+      }
+      for &dep in tree.get(&id).map(AsRef::as_ref).into_iter().flatten() {
+        if let Entry::Vacant(entry) = parent.entry(dep) {
+          entry.insert(id);
+          queue.push_back(dep);
+        }
+      }
+    }
+    None
+  }
+}
+
+/// Guard over a batch of builder edits. `commit` keeps the edits only if the
+/// graph is still acyclic; `rollback`, a failed `commit` or dropping the guard
+/// restores the state captured by `DepTreeBuilder::begin`: units, edges and
+/// their data, attributes, rules, options and observers registered since.
+#[derive(Debug)]
+pub struct Transaction {
+  builder: DepTreeBuilder,
+  snapshot: Option<Snapshot>,
 }
 
+/// Everything a `Transaction` puts back, observers by count.
+#[derive(Debug)]
+struct Snapshot {
+  inner: Box<DepMap>,
+  observers: usize,
+  options: Options,
+  duplicates: usize,
+  edges: EdgeData,
+  attrs: Attrs,
+  rules: Rules,
+}
+
+impl Transaction {
+  pub fn commit(mut self) -> DepTreeBuilderResult<()> {
+    let result = {
+      let inner = self.builder.inner.try_borrow().unwrap();
+      DepTreeBuilder::check(&inner, self.builder.options.get().all_cycles)
+    };
+    if result.is_ok() {
+      self.snapshot = None;
+    }
+    result
+  }
+
+  pub fn rollback(self) {}
+}
+
+impl Drop for Transaction {
+  fn drop(&mut self) {
+    if let Some(snapshot) = self.snapshot.take() {
+      let mut inner_lock = self.builder.inner.try_borrow_mut().unwrap();
+      let events = ChangeEvent::diff(&inner_lock, &snapshot.inner);
+      *inner_lock = snapshot.inner;
+      drop(inner_lock);
+      self.builder.observers.try_borrow_mut().unwrap().0.truncate(snapshot.observers);
+      self.builder.options.set(snapshot.options);
+      self.builder.duplicates.set(snapshot.duplicates);
+      *self.builder.edges.try_borrow_mut().unwrap() = snapshot.edges;
+      *self.builder.attrs.try_borrow_mut().unwrap() = snapshot.attrs;
+      *self.builder.rules.try_borrow_mut().unwrap() = snapshot.rules;
+      self.builder.notify(&events);
+    }
+  }
+}
+
+/// Built, acyclic dependency graph. Only the adjacency lists are shared:
+/// trees derived through `with_dep`, `without_dep` and `without_unit` point
+/// at the lists they didn't touch instead of copying them, but each one gets
+/// its own map from unit to list. Every version therefore costs a pointer per
+/// unit, O(units), though not a copy of the edges.
 #[derive(Clone, Debug, Default)]
 pub struct DepTree {
-  inner: Rc<BTreeMap<DepId, Vec<DepId>>>,
+  inner: Rc<SharedDepMap>,
+  edges: Rc<EdgeData>,
+  attrs: Rc<Attrs>,
+  report: Rc<BuildReport>,
 }
 
 impl DepTree {
   pub fn new(inner: Rc<BTreeMap<DepId, Vec<DepId>>>) -> Self {
-    Self { inner }
+    Self::from_map(&inner)
+  }
+
+  fn from_map(map: &DepMap) -> Self {
+    Self {
+      inner: Rc::new(
+        map
+          .iter()
+          .map(|(&id, deps)| (id, Rc::from(deps.as_slice())))
+          .collect(),
+      ),
+      edges: Rc::default(),
+      attrs: Rc::default(),
+      report: Rc::default(),
+    }
+  }
+
+  /// Tree over `inner` keeping whatever else `self` tracks for the edges that
+  /// are still there. Attributes are only dropped for units among `dropped`,
+  /// the ones the change may have removed, that are really gone.
+  fn derive(&self, inner: SharedDepMap, dropped: impl IntoIterator<Item = DepId>) -> Self {
+    let edges = match self.edges.retain_present(&inner) {
+      Some(edges) => Rc::new(edges),
+      None => self.edges.clone(),
+    };
+    let mut gone = dropped
+      .into_iter()
+      .filter(|id| self.attrs.contains_key(id) && !inner.contains_key(id))
+      .collect::<BTreeSet<_>>();
+    for dep in inner.values().flat_map(|deps| deps.iter()) {
+      if gone.is_empty() {
+        break;
+      }
+      gone.remove(dep);
+    }
+    let attrs = if gone.is_empty() {
+      self.attrs.clone()
+    } else {
+      Rc::new(self.attrs.iter().filter(|(id, _)| !gone.contains(id)).map(|(&id, attrs)| (id, attrs.clone())).collect())
+    };
+    Self { inner: Rc::new(inner), edges, attrs, report: self.report.clone() }
+  }
+
+  /// How often `dep` was declared as a dependency of `id`, `0` when it isn't one.
+  pub fn edge_count(&self, id: DepId, dep: DepId) -> usize {
+    match self.edges.counts.get(&(id, dep)) {
+      Some(&count) => count,
+      None => self.direct(id).iter().filter(|&&d| d == dep).count(),
+    }
+  }
+
+  pub fn attr(&self, id: DepId, key: &str) -> Option<&AttrValue> {
+    self.attrs.get(&id)?.get(key)
+  }
+
+  /// Whether `id` was pinned with `DepTreeBuilder::pin`.
+  pub fn is_pinned(&self, id: DepId) -> bool {
+    self.attr(id, PINNED).and_then(AttrValue::as_bool).unwrap_or(false)
+  }
+
+  /// First pinned unit among `units`, as an error.
+  fn check_unpinned(&self, units: impl IntoIterator<Item = DepId>) -> DepTreeBuilderResult<()> {
+    match units.into_iter().find(|&id| self.is_pinned(id)) {
+      Some(id) => Err(DepTreeBuilderError::Pinned(id)),
+      None => Ok(()),
+    }
+  }
+
+  /// Every attribute set on `id`, see `DepTreeBuilder::set_attr`.
+  pub fn attrs(&self, id: DepId) -> impl Iterator<Item = (&str, &AttrValue)> {
+    self.attrs.get(&id).into_iter().flatten().map(|(key, value)| (key.as_str(), value))
+  }
+
+  /// Payload attached to the `id -> dep` edge with `DepTreeBuilder::with_edge`,
+  /// `None` when there is none or it isn't an `E`.
+  pub fn edge<E: Any>(&self, id: DepId, dep: DepId) -> Option<&E> {
+    self.edges.payloads.get(&(id, dep))?.downcast_ref()
+  }
+
+  /// Groups the `id -> dep` edge was put in with `DepTreeBuilder::in_group`.
+  pub fn groups(&self, id: DepId, dep: DepId) -> impl Iterator<Item = &str> {
+    self.edges.groups.get(&(id, dep)).into_iter().flatten().map(String::as_str)
+  }
+
+  /// Effective tree for one activation set: edges in no group, plus the
+  /// edges in at least one of the `active` groups.
+  pub fn with_groups(&self, active: &[&str]) -> Self {
+    let enabled = |id: DepId, dep: DepId| self.in_active_group(id, dep, active);
+    let inner = self
+      .inner
+      .iter()
+      .map(|(&id, deps)| {
+        if deps.iter().all(|&dep| enabled(id, dep)) {
+          (id, deps.clone())
+        } else {
+          (id, deps.iter().copied().filter(|&dep| enabled(id, dep)).collect())
+        }
+      })
+      .collect();
+    self.derive(inner, self.edges.groups.keys().map(|&(_, dep)| dep))
+  }
+
+  /// Every unit, each after the dependencies it reaches over edges active for
+  /// the `kinds` groups, as `with_groups(kinds)` would keep them. Whenever
+  /// several units are ready, the smallest `DepId` goes first.
+  pub fn topological_order_for(&self, kinds: &[&str]) -> Vec<DepId> {
+    let dependents = self.reverse_map();
+    let active = |id: DepId| {
+      self.direct(id).iter().copied().filter(|&dep| self.in_active_group(id, dep, kinds)).collect::<BTreeSet<_>>()
+    };
+    let mut pending = dependents.keys().map(|&id| (id, active(id).len())).collect::<BTreeMap<_, _>>();
+    let mut ready = pending.iter().filter(|(_, &count)| count == 0).map(|(&id, _)| id).collect::<BTreeSet<_>>();
+    let mut order = Vec::with_capacity(pending.len());
+    while let Some(id) = ready.pop_first() {
+      order.push(id);
+      for &dependent in dependents[&id].iter().collect::<BTreeSet<_>>() {
+        if self.in_active_group(dependent, id, kinds) {
+          let count = pending.get_mut(&dependent).unwrap();
+          *count -= 1;
+          if *count == 0 {
+            ready.insert(dependent);
+          }
+        }
+      }
+    }
+    order
+  }
+
+  fn in_active_group(&self, id: DepId, dep: DepId, active: &[&str]) -> bool {
+    self.edges.groups.get(&(id, dep)).is_none_or(|groups| active.iter().any(|&group| groups.contains(group)))
+  }
+
+  pub fn with_dep(&self, id: DepId, deps: Vec<DepId>) -> DepTreeBuilderResult<Self> {
+    DepTreeBuilder::check_new_deps(id, &deps, &self.inner)?;
+    let mut inner = (*self.inner).clone();
+    let merged = match inner.get(&id) {
+      Some(existing) => existing.iter().copied().chain(deps).collect(),
+      None => deps,
+    };
+    inner.insert(id, Rc::from(merged));
+    Ok(self.derive(inner, []))
+  }
+
+  pub fn without_dep(&self, id: DepId, dep: DepId) -> Self {
+    match self.inner.get(&id) {
+      Some(deps) if deps.contains(&dep) => {
+        let mut inner = (*self.inner).clone();
+        inner.insert(id, deps.iter().copied().filter(|&d| d != dep).collect());
+        self.derive(inner, [dep])
+      }
+      _ => self.clone(),
+    }
+  }
+
+  pub fn without_unit(&self, id: DepId) -> Self {
+    let mut inner = (*self.inner).clone();
+    let orphaned = inner.remove(&id).unwrap_or_else(|| Rc::from([]));
+    for deps in inner.values_mut() {
+      if deps.contains(&id) {
+        *deps = deps.iter().copied().filter(|&d| d != id).collect();
+      }
+    }
+    self.derive(inner, orphaned.iter().copied().chain([id]))
+  }
+
+  /// Merges `nodes` into the single unit `new_id`, which keeps every edge
+  /// leaving or entering the set and none of the edges inside it. If `new_id`
+  /// is already a unit it is merged as well. Fails when the merge closes a
+  /// cycle, i.e. when a path leaves the set and comes back, or when one of
+  /// the merged units is pinned.
+  pub fn contract(&self, nodes: impl IntoIterator<Item = DepId>, new_id: DepId) -> DepTreeBuilderResult<Self> {
+    let mut members = nodes.into_iter().collect::<BTreeSet<_>>();
+    members.insert(new_id);
+    self.check_unpinned(members.iter().copied())?;
+    let rename = |id: DepId| if members.contains(&id) { new_id } else { id };
+    let mut contracted = DepMap::new();
+    for (&id, deps) in self.inner.iter() {
+      let list = contracted.entry(rename(id)).or_default();
+      for &dep in deps.iter() {
+        let dep = rename(dep);
+        if dep != rename(id) && !list.contains(&dep) {
+          list.push(dep);
+        }
+      }
+    }
+    contracted.entry(new_id).or_default();
+    DepTreeBuilder::validate(&contracted)?;
+    let inner = contracted
+      .into_iter()
+      .map(|(id, deps)| match self.inner.get(&id) {
+        Some(existing) if **existing == *deps => (id, existing.clone()),
+        _ => (id, Rc::from(deps)),
+      })
+      .collect();
+    Ok(self.derive(inner, members.iter().copied().filter(|&id| id != new_id)))
+  }
+
+  /// Replaces `id` with `parts`, each declared with its own dependencies.
+  /// Every dependent `d` of `id` depends on `dependent_mapping(d)` instead,
+  /// normally a subset of the parts. Fails if the result is cyclic or `id`
+  /// is pinned.
+  pub fn split(
+    &self,
+    id: DepId,
+    parts: Vec<(DepId, Vec<DepId>)>,
+    dependent_mapping: impl Fn(DepId) -> Vec<DepId>,
+  ) -> DepTreeBuilderResult<Self> {
+    self.check_unpinned([id])?;
+    let mut inner = (*self.inner).clone();
+    let orphaned = inner.remove(&id).unwrap_or_else(|| Rc::from([]));
+    for (&unit, deps) in inner.iter_mut() {
+      if deps.contains(&id) {
+        let replacement = dependent_mapping(unit);
+        *deps = deps
+          .iter()
+          .flat_map(|&dep| if dep == id { replacement.clone() } else { vec![dep] })
+          .collect();
+      }
+    }
+    for (part, deps) in parts {
+      let deps = match inner.get(&part) {
+        Some(existing) => existing.iter().copied().chain(deps).collect(),
+        None => deps,
+      };
+      inner.insert(part, Rc::from(deps));
+    }
+    DepTreeBuilder::validate(&inner)?;
+    Ok(self.derive(inner, orphaned.iter().copied().chain([id])))
+  }
+
+  /// Renames `old` to `new`, keeping its edges in both directions along with
+  /// its attributes and edge data. A pinned `old` is not renamed.
+  pub fn replace_id(&self, old: DepId, new: DepId) -> DepTreeBuilderResult<Self> {
+    self.check_unpinned([old])?;
+    let exists = |id: DepId| self.inner.contains_key(&id) || self.inner.values().any(|deps| deps.contains(&id));
+    if !exists(old) {
+      return Err(DepTreeBuilderError::UnknownUnit(old));
+    }
+    if exists(new) {
+      return Err(DepTreeBuilderError::DuplicateUnit(new));
+    }
+    let rename = |id: DepId| if id == old { new } else { id };
+    let mut inner = (*self.inner).clone();
+    if let Some(deps) = inner.remove(&old) {
+      inner.insert(new, deps);
+    }
+    for deps in inner.values_mut() {
+      if deps.contains(&old) {
+        *deps = deps.iter().map(|&dep| rename(dep)).collect();
+      }
+    }
+    let mut edges = (*self.edges).clone();
+    let rename_edge = |(id, dep): Edge| (rename(id), rename(dep));
+    edges.counts = edges.counts.into_iter().map(|(edge, count)| (rename_edge(edge), count)).collect();
+    edges.payloads = edges.payloads.into_iter().map(|(edge, payload)| (rename_edge(edge), payload)).collect();
+    edges.groups = edges.groups.into_iter().map(|(edge, groups)| (rename_edge(edge), groups)).collect();
+    edges.targets = edges.targets.into_iter().map(|(edge, exprs)| (rename_edge(edge), exprs)).collect();
+    let mut attrs = (*self.attrs).clone();
+    if let Some(unit_attrs) = attrs.remove(&old) {
+      attrs.insert(new, unit_attrs);
+    }
+    Ok(Self { inner: Rc::new(inner), edges: Rc::new(edges), attrs: Rc::new(attrs), report: self.report.clone() })
+  }
+
+  /// Tree with every edge pointing the other way, so each unit depends on
+  /// its former dependents. Edge data moves along with the edges.
+  pub fn reversed(&self) -> Self {
+    let inner = self.reverse_map().into_iter().map(|(id, deps)| (id, Rc::from(deps))).collect();
+    let flip = |&(id, dep): &Edge| (dep, id);
+    let edges = EdgeData {
+      counts: self.edges.counts.iter().map(|(edge, &count)| (flip(edge), count)).collect(),
+      payloads: self.edges.payloads.iter().map(|(edge, payload)| (flip(edge), payload.clone())).collect(),
+      groups: self.edges.groups.iter().map(|(edge, groups)| (flip(edge), groups.clone())).collect(),
+      targets: self.edges.targets.iter().map(|(edge, exprs)| (flip(edge), exprs.clone())).collect(),
+    };
+    Self { inner: Rc::new(inner), edges: Rc::new(edges), attrs: self.attrs.clone(), report: self.report.clone() }
+  }
+
+  /// Keeps only the units reachable from `roots` or from a pinned unit,
+  /// returning the pruned tree and every unit that was dropped. A root or
+  /// pinned unit that only appeared as a dependency is declared if nothing
+  /// kept depends on it.
+  pub fn prune_unreachable(&self, roots: impl IntoIterator<Item = DepId>) -> (Self, Vec<DepId>) {
+    let units = self.reverse_map();
+    let mut reachable = BTreeSet::new();
+    let mut order = Vec::new();
+    for root in roots {
+      if units.contains_key(&root) {
+        self.post_order(root, &mut reachable, &mut order);
+      }
+    }
+    for id in self.attrs.keys().copied().filter(|&id| self.is_pinned(id)) {
+      if units.contains_key(&id) {
+        self.post_order(id, &mut reachable, &mut order);
+      }
+    }
+    let removed = units.into_keys().filter(|id| !reachable.contains(id)).collect::<Vec<_>>();
+    let mut inner = self
+      .inner
+      .iter()
+      .filter(|(id, _)| reachable.contains(id))
+      .map(|(&id, deps)| (id, deps.clone()))
+      .collect::<SharedDepMap>();
+    // Undeclared units kept only as roots have nothing left pointing at them.
+    let referenced = inner.values().flat_map(|deps| deps.iter().copied()).collect::<BTreeSet<_>>();
+    for &id in &reachable {
+      if !inner.contains_key(&id) && !referenced.contains(&id) {
+        inner.insert(id, Rc::from([]));
+      }
+    }
+    (self.derive(inner, removed.iter().copied()), removed)
   }
-  
-  pub fn most_dependencies(&self) -> Vec<(DepId, usize)> {
-    let mut dependency_counts = self.inner.keys().map(|id| {
-      let count = self.count_dependencies(id, &mut BTreeSet::new());
-      (*id, count)
-    }).collect::<Vec<_>>();
 
-    dependency_counts.sort_by(|a, b| b.1.cmp(&a.1));
-    dependency_counts
+  /// Applies `changes` as one step and validates the result, so intermediate
+  /// states of the batch may be cyclic.
+  pub fn apply(&self, changes: &[ChangeEvent]) -> DepTreeBuilderResult<Self> {
+    let mut inner = (*self.inner).clone();
+    let mut dropped = Vec::new();
+    for change in changes {
+      match *change {
+        ChangeEvent::UnitAdded(id) => {
+          inner.entry(id).or_insert_with(|| Rc::from([]));
+        }
+        ChangeEvent::UnitRemoved(id) => {
+          dropped.push(id);
+          dropped.extend(inner.remove(&id).iter().flat_map(|deps| deps.iter()));
+          for deps in inner.values_mut() {
+            if deps.contains(&id) {
+              *deps = deps.iter().copied().filter(|&d| d != id).collect();
+            }
+          }
+        }
+        ChangeEvent::EdgeAdded(id, dep) => {
+          let deps = inner.entry(id).or_insert_with(|| Rc::from([]));
+          *deps = deps.iter().copied().chain([dep]).collect();
+        }
+        ChangeEvent::EdgeRemoved(id, dep) => {
+          if let Some(deps) = inner.get_mut(&id) {
+            if deps.contains(&dep) {
+              dropped.push(dep);
+              *deps = deps.iter().copied().filter(|&d| d != dep).collect();
+            }
+          }
+        }
+      }
+    }
+    DepTreeBuilder::validate(&inner)?;
+    Ok(self.derive(inner, dropped))
   }
 
+  /// Removes then adds edges as one step like `apply`, returning the new tree
+  /// with every unit whose transitive dependencies changed. Edges already
+  /// present are not added twice.
+  pub fn apply_delta(
+    &self,
+    added: &[(DepId, DepId)],
+    removed: &[(DepId, DepId)],
+  ) -> DepTreeBuilderResult<(Self, BTreeSet<DepId>)> {
+    let present = |tree: &Self, (id, dep): Edge| tree.direct(id).contains(&dep);
+    let removed = removed.iter().copied().filter(|&edge| present(self, edge)).collect::<BTreeSet<_>>();
+    let added = added
+      .iter()
+      .copied()
+      .filter(|&edge| !present(self, edge) || removed.contains(&edge))
+      .collect::<BTreeSet<_>>();
+    let changes = removed
+      .iter()
+      .map(|&(id, dep)| ChangeEvent::EdgeRemoved(id, dep))
+      .chain(added.iter().map(|&(id, dep)| ChangeEvent::EdgeAdded(id, dep)))
+      .collect::<Vec<_>>();
+    let tree = self.apply(&changes)?;
+
+    // Only the changed edges' sources and their dependents, before or after,
+    // can see a different closure.
+    let mut candidates = added.iter().chain(&removed).map(|&(id, _)| id).collect::<BTreeSet<_>>();
+    for reverse in [self.reverse_map(), tree.reverse_map()] {
+      let sources = candidates.iter().copied().collect::<Vec<_>>();
+      for id in sources {
+        candidates.extend(bfs(id, None, |id| reverse.get(&id).into_iter().flatten().copied()).into_keys());
+      }
+    }
+    let closure = |tree: &Self, id: DepId| {
+      bfs(id, None, |id| tree.direct(id).iter().copied()).into_keys().collect::<BTreeSet<_>>()
+    };
+    let affected = candidates.into_iter().filter(|&id| closure(self, id) != closure(&tree, id)).collect();
+    Ok((tree, affected))
+  }
+
+  /// Whether both trees hold the very same allocation for `id`'s dependency list.
+  pub fn shares_deps_with(&self, other: &DepTree, id: DepId) -> bool {
+    match (self.inner.get(&id), other.inner.get(&id)) {
+      (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+      _ => false,
+    }
+  }
+
+  /// Units by their number of distinct transitive dependencies, ties broken
+  /// by ascending `DepId`.
+  pub fn most_dependencies(&self) -> Vec<(DepId, usize)> {
+    self.rank(Metric::Dependencies, most)
+  }
+
+  /// Units by their number of direct dependents, see
+  /// `most_transitive_dependents` for everything that reaches them.
   pub fn most_dependents(&self) -> Vec<(DepId, usize)> {
-    let mut dependent_counts = self.calculate_dependents();
-    dependent_counts.sort_by(|a, b| b.1.cmp(&a.1));
-    dependent_counts
+    self.rank(Metric::Dependents, most)
   }
 
   pub fn least_dependencies(&self) -> Vec<(DepId, usize)> {
-    let mut dependency_counts = self.inner.keys().map(|id| {
-      let count = self.count_dependencies(id, &mut BTreeSet::new());
-      (*id, count)
-    }).collect::<Vec<_>>();
-
-    dependency_counts.sort_by(|a, b| a.1.cmp(&b.1));
-    dependency_counts
+    self.rank(Metric::Dependencies, least)
   }
 
+  /// Units by their number of direct dependents, fewest first.
   pub fn least_dependents(&self) -> Vec<(DepId, usize)> {
-    let mut dependent_counts = self.calculate_dependents();
-    dependent_counts.sort_by(|a, b| a.1.cmp(&b.1));
-    dependent_counts
+    self.rank(Metric::Dependents, least)
+  }
+
+  /// Units by the number of distinct units depending on them directly or
+  /// indirectly, unlike `most_dependents` which only counts direct ones.
+  pub fn most_transitive_dependents(&self) -> Vec<(DepId, usize)> {
+    self.rank(Metric::TransitiveDependents, most)
+  }
+
+  /// Like `most_transitive_dependents`, fewest first.
+  pub fn least_transitive_dependents(&self) -> Vec<(DepId, usize)> {
+    self.rank(Metric::TransitiveDependents, least)
+  }
+
+  /// Counts `metric` for every unit it applies to and sorts the result with
+  /// `compare`. The `most_*` and `least_*` rankings order by count and then by
+  /// ascending `DepId`.
+  pub fn rank(
+    &self,
+    metric: Metric,
+    mut compare: impl FnMut(&(DepId, usize), &(DepId, usize)) -> Ordering,
+  ) -> Vec<(DepId, usize)> {
+    let mut counts = match metric {
+      Metric::Dependencies => {
+        let sizes = closure_sizes(&self.inner);
+        self.inner.keys().map(|id| (*id, sizes[id])).collect::<Vec<_>>()
+      }
+      Metric::Dependents => self.calculate_dependents(),
+      Metric::TransitiveDependents => closure_sizes(&self.reverse_map()).into_iter().collect(),
+    };
+    counts.sort_by(|a, b| compare(a, b));
+    counts
   }
 
   pub fn dependencies_of(&self, unit: DepId) -> Vec<DepId> {
@@ -159,19 +1371,122 @@ impl DepTree {
       .collect()
   }
 
-  fn count_dependencies(&self, id: &DepId, visited: &mut BTreeSet<DepId>) -> usize {
-    if !visited.insert(*id) {
-      return 0;
+  /// Whether `a` transitively depends on `b`, stopping at the first path found.
+  pub fn depends_on(&self, a: DepId, b: DepId) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut stack = vec![a];
+    while let Some(id) = stack.pop() {
+      let Some(deps) = self.inner.get(&id) else {
+        continue;
+      };
+      for &dep in deps.iter() {
+        if dep == b {
+          return true;
+        }
+        if visited.insert(dep) {
+          stack.push(dep);
+        }
+      }
     }
-    self.inner
-      .get(id)
-      .map(|deps| {
-        deps
-          .iter()
-          .map(|dep| 1 + self.count_dependencies(dep, visited))
-          .sum()
-      })
-      .unwrap_or(0)
+    false
+  }
+
+  /// Whether `b` transitively depends on `a`.
+  pub fn is_depended_on_by(&self, a: DepId, b: DepId) -> bool {
+    self.depends_on(b, a)
+  }
+
+  /// Fewest dependency edges to follow from `a` to reach `b`.
+  pub fn distance(&self, a: DepId, b: DepId) -> Option<usize> {
+    let mut visited = BTreeSet::from([a]);
+    let mut queue = VecDeque::from([(a, 0)]);
+    while let Some((id, hops)) = queue.pop_front() {
+      if id == b {
+        return Some(hops);
+      }
+      for &dep in self.inner.get(&id).into_iter().flat_map(|deps| deps.iter()) {
+        if visited.insert(dep) {
+          queue.push_back((dep, hops + 1));
+        }
+      }
+    }
+    None
+  }
+
+  /// Number of distinct dependency paths from `unit` to each of its transitive
+  /// dependencies, so a unit reached through a diamond counts once per path.
+  /// Counts saturate at `u64::MAX`.
+  pub fn dependency_multiplicities(&self, unit: DepId) -> BTreeMap<DepId, u64> {
+    let mut order = Vec::new();
+    self.post_order(unit, &mut BTreeSet::new(), &mut order);
+    let mut paths = BTreeMap::from([(unit, 1u64)]);
+    for &id in order.iter().rev() {
+      let count = paths[&id];
+      for &dep in self.direct(id) {
+        let entry = paths.entry(dep).or_insert(0);
+        *entry = entry.saturating_add(count);
+      }
+    }
+    paths.remove(&unit);
+    paths
+  }
+
+  /// Total number of dependency paths starting at `unit`, the multiplicity
+  /// counterpart of the unique count in `most_dependencies`.
+  pub fn count_dependency_paths(&self, unit: DepId) -> u64 {
+    self
+      .dependency_multiplicities(unit)
+      .into_values()
+      .fold(0, u64::saturating_add)
+  }
+
+  /// Everything at most `k` edges away from `unit` in `direction`, excluding
+  /// `unit`. With `Direction::Both` paths may mix both edge directions.
+  pub fn neighborhood(&self, unit: DepId, k: usize, direction: Direction) -> BTreeSet<DepId> {
+    let dependents = match direction {
+      Direction::Dependencies => BTreeMap::new(),
+      Direction::Dependents | Direction::Both => self.reverse_map(),
+    };
+    let dependents_of = |id: DepId| dependents.get(&id).map_or(&[][..], Vec::as_slice);
+    let reached = bfs(unit, Some(k), |id| {
+      let (down, up): (&[DepId], &[DepId]) = match direction {
+        Direction::Dependencies => (self.direct(id), &[]),
+        Direction::Dependents => (&[], dependents_of(id)),
+        Direction::Both => (self.direct(id), dependents_of(id)),
+      };
+      down.iter().chain(up).copied()
+    });
+    reached.into_keys().collect()
+  }
+
+  /// Units reachable from `id` (itself included), each after all of its
+  /// dependencies.
+  fn post_order(&self, id: DepId, visited: &mut BTreeSet<DepId>, order: &mut Vec<DepId>) {
+    if !visited.insert(id) {
+      return;
+    }
+    for &dep in self.direct(id) {
+      self.post_order(dep, visited, order);
+    }
+    order.push(id);
+  }
+
+  /// Direct dependencies of `id`, empty for undeclared units.
+  fn direct(&self, id: DepId) -> &[DepId] {
+    self.inner.get(&id).map_or(&[], |deps| deps)
+  }
+
+  /// Transitive dependencies of `unit` at most `max_depth` edges away, with the
+  /// depth each one is first reached at (`1` for direct dependencies).
+  pub fn dependencies_within(&self, unit: DepId, max_depth: usize) -> BTreeMap<DepId, usize> {
+    bfs(unit, Some(max_depth), |id| self.direct(id).iter().copied())
+  }
+
+  /// Transitive dependents of `unit` at most `max_depth` edges away, with the
+  /// depth each one is first reached at (`1` for direct dependents).
+  pub fn dependents_within(&self, unit: DepId, max_depth: usize) -> BTreeMap<DepId, usize> {
+    let dependents = self.reverse_map();
+    bfs(unit, Some(max_depth), |id| dependents.get(&id).into_iter().flatten().copied())
   }
 
   fn collect_dependencies(&self, id: &DepId, visited: &mut BTreeSet<DepId>, dependencies: &mut Vec<DepId>) {
@@ -179,7 +1494,7 @@ impl DepTree {
       return;
     }
     if let Some(deps) = self.inner.get(id) {
-      for dep in deps {
+      for dep in deps.iter() {
         dependencies.push(*dep);
         self.collect_dependencies(dep, visited, dependencies);
       }
@@ -190,7 +1505,7 @@ impl DepTree {
     let mut dependent_map: BTreeMap<DepId, usize> = BTreeMap::new();
     
     for (&key, deps) in self.inner.iter() {
-      for &dep in deps {
+      for &dep in deps.iter() {
         *dependent_map.entry(dep).or_insert(0) += 1;
       }
       dependent_map.entry(key).or_insert(0);
@@ -198,4 +1513,91 @@ impl DepTree {
 
     dependent_map.into_iter().collect()
   }
-}
\ No newline at end of file
+
+  /// Longest path from every unit down to a leaf, leaves being level `0`.
+  fn levels(&self) -> BTreeMap<DepId, usize> {
+    let mut levels = BTreeMap::new();
+    for &id in self.reverse_map().keys() {
+      self.level_of(id, &mut levels);
+    }
+    levels
+  }
+
+  fn level_of(&self, id: DepId, levels: &mut BTreeMap<DepId, usize>) -> usize {
+    if let Some(&level) = levels.get(&id) {
+      return level;
+    }
+    let level = self
+      .inner
+      .get(&id)
+      .and_then(|deps| deps.iter().map(|&dep| 1 + self.level_of(dep, levels)).max())
+      .unwrap_or(0);
+    levels.insert(id, level);
+    level
+  }
+
+  /// Direct dependents of every unit, including the ones that only appear as
+  /// a dependency.
+  fn reverse_map(&self) -> DepMap {
+    let mut reverse: DepMap = BTreeMap::new();
+    for (&key, deps) in self.inner.iter() {
+      reverse.entry(key).or_default();
+      for &dep in deps.iter() {
+        reverse.entry(dep).or_default().push(key);
+      }
+    }
+    reverse
+  }
+
+  /// Every unit and the set of edges between them, the part of the tree
+  /// `==` and `canonical_hash` look at.
+  fn structure(&self) -> (BTreeSet<DepId>, BTreeSet<Edge>) {
+    let mut units = BTreeSet::new();
+    let mut edges = BTreeSet::new();
+    for (&id, deps) in self.inner.iter() {
+      units.insert(id);
+      for &dep in deps.iter() {
+        units.insert(dep);
+        edges.insert((id, dep));
+      }
+    }
+    (units, edges)
+  }
+
+  /// Fingerprint of the units and edges, equal for trees that are `==` and
+  /// stable across runs, platforms and crate versions: FNV-1a over the
+  /// sorted units and edges.
+  pub fn canonical_hash(&self) -> u64 {
+    let (units, edges) = self.structure();
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut write = |value: u64| {
+      for byte in value.to_le_bytes() {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+      }
+    };
+    write(units.len() as u64);
+    for (id, version) in units {
+      write(id);
+      write(version as u64);
+    }
+    write(edges.len() as u64);
+    for ((id, version), (dep, dep_version)) in edges {
+      write(id);
+      write(version as u64);
+      write(dep);
+      write(dep_version as u64);
+    }
+    hash
+  }
+}
+
+/// Trees are equal when they have the same units and the same edges, no
+/// matter the order of dependency lists, repeated edges, attributes or edge
+/// data.
+impl PartialEq for DepTree {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.inner, &other.inner) || self.structure() == other.structure()
+  }
+}
+
+impl Eq for DepTree {}
\ No newline at end of file
@@ -0,0 +1,216 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+  import::{ImportError, ImportResult},
+  AttrValue, DepId, DepTree, DepTreeBuilder,
+};
+
+const LOCKFILE_VERSION: u32 = 1;
+
+fn quote(value: &str) -> String {
+  let mut quoted = String::from('"');
+  for c in value.chars() {
+    match c {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\t' => quoted.push_str("\\t"),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+fn unquote(text: &str) -> Option<String> {
+  let mut chars = text.strip_prefix('"')?.strip_suffix('"')?.chars();
+  let mut value = String::new();
+  while let Some(c) = chars.next() {
+    value.push(match c {
+      '\\' => match chars.next()? {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c @ ('"' | '\\') => c,
+        _ => return None,
+      },
+      '"' => return None,
+      c => c,
+    });
+  }
+  Some(value)
+}
+
+/// Splits a leading quoted string off `text`, returning it still quoted along
+/// with whatever follows the closing quote.
+fn split_quoted(text: &str) -> Option<(&str, &str)> {
+  let mut chars = text.strip_prefix('"')?.char_indices();
+  while let Some((index, c)) = chars.next() {
+    match c {
+      '\\' => {
+        chars.next()?;
+      }
+      '"' => return Some(text.split_at(index + 2)),
+      _ => {}
+    }
+  }
+  None
+}
+
+fn parse_unit(fields: &[&str]) -> Option<DepId> {
+  match fields {
+    [id, version] => Some((id.parse().ok()?, version.parse().ok()?)),
+    _ => None,
+  }
+}
+
+impl DepTree {
+  /// Writes the tree as a lockfile: a version line, then every unit in
+  /// `DepId` order with its sorted dependencies, repeats kept, and its
+  /// attributes, one per line. Undeclared leaves are written as `leaf` lines
+  /// so they stay undeclared when read back. The same tree always produces
+  /// the same bytes, e.g. `unit 1 0`, `  dep 2 0`, `  attr "checksum" "ab12"`.
+  /// Edge data is not written.
+  pub fn to_lockfile<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "# Generated by dep-tree. Do not edit by hand.")?;
+    writeln!(writer, "lockfile {LOCKFILE_VERSION}")?;
+    for &(id, version) in self.reverse_map().keys() {
+      let keyword = if self.inner.contains_key(&(id, version)) { "unit" } else { "leaf" };
+      writeln!(writer, "{keyword} {id} {version}")?;
+      let mut deps = self.direct((id, version)).to_vec();
+      deps.sort();
+      for (id, version) in deps {
+        writeln!(writer, "  dep {id} {version}")?;
+      }
+      for (key, value) in self.attrs((id, version)) {
+        let value = match value {
+          AttrValue::String(value) => quote(value),
+          AttrValue::Int(value) => value.to_string(),
+          AttrValue::Bool(value) => value.to_string(),
+        };
+        writeln!(writer, "  attr {} {value}", quote(key))?;
+      }
+    }
+    writer.flush()
+  }
+
+  /// Reads a lockfile written by `to_lockfile`, rejecting other lockfile
+  /// versions and cyclic contents.
+  pub fn from_lockfile(mut reader: impl Read) -> ImportResult<Self> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let mut builder = DepTreeBuilder::new();
+    let mut unit = None;
+    let mut declared = false;
+    let mut versioned = false;
+    for (index, line) in text.lines().enumerate() {
+      let error = |message: &str| ImportError::Line { line: index + 1, message: message.to_owned() };
+      let trimmed = line.trim();
+      if trimmed.is_empty() || trimmed.starts_with('#') {
+        continue;
+      }
+      let (keyword, rest) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+      if !versioned {
+        if keyword != "lockfile" {
+          return Err(error("expected the `lockfile` version line"));
+        }
+        if rest.trim() != LOCKFILE_VERSION.to_string() {
+          return Err(ImportError::Invalid(format!("unsupported lockfile version `{}`", rest.trim())));
+        }
+        versioned = true;
+        continue;
+      }
+      match keyword {
+        "unit" | "leaf" => {
+          let id = parse_unit(&rest.split_whitespace().collect::<Vec<_>>())
+            .ok_or_else(|| error(&format!("expected `{keyword} <id> <version>`")))?;
+          declared = keyword == "unit";
+          if declared {
+            builder.with_dep(id, Vec::new());
+          }
+          unit = Some(id);
+        }
+        "dep" => {
+          let unit = unit.ok_or_else(|| error("`dep` before any `unit`"))?;
+          if !declared {
+            return Err(error("`dep` under a `leaf`"));
+          }
+          let dep = parse_unit(&rest.split_whitespace().collect::<Vec<_>>()).ok_or_else(|| error("expected `dep <id> <version>`"))?;
+          builder.with_dep(unit, vec![dep]);
+        }
+        "attr" => {
+          let unit = unit.ok_or_else(|| error("`attr` before any `unit`"))?;
+          let (key, value) = split_quoted(rest)
+            .and_then(|(key, value)| Some((unquote(key)?, value.strip_prefix(' ')?)))
+            .ok_or_else(|| error("expected `attr \"<key>\" <value>`"))?;
+          let value = match value {
+            "true" => AttrValue::Bool(true),
+            "false" => AttrValue::Bool(false),
+            value if value.starts_with('"') => AttrValue::String(unquote(value).ok_or_else(|| error("invalid string"))?),
+            value => AttrValue::Int(value.parse().map_err(|_| error("expected a string, integer or boolean"))?),
+          };
+          builder.set_attr(unit, key, value);
+        }
+        _ => return Err(error("unknown entry")),
+      }
+    }
+    if !versioned {
+      return Err(ImportError::Invalid("missing the `lockfile` version line".to_owned()));
+    }
+    builder.build().map(|tree| *tree).map_err(|error| ImportError::Invalid(error.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn lockfile(tree: &DepTree) -> String {
+    let mut bytes = Vec::new();
+    tree.to_lockfile(&mut bytes).unwrap();
+    String::from_utf8(bytes).unwrap()
+  }
+
+  fn read(text: &str) -> ImportResult<DepTree> {
+    DepTree::from_lockfile(text.as_bytes())
+  }
+
+  #[test]
+  fn round_trips() {
+    let mut builder = DepTreeBuilder::new();
+    builder.with_dep((2, 0), vec![(1, 0), (3, 1), (3, 1)]);
+    builder.with_dep((1, 0), vec![(3, 1)]);
+    builder.set_attr((1, 0), "note", "a \"quoted\"\n\\ value");
+    builder.set_attr((1, 0), "size", -4);
+    builder.set_attr((3, 1), "pinned", true);
+    let tree = *builder.build().unwrap();
+    let text = lockfile(&tree);
+    assert_eq!(
+      text,
+      "# Generated by dep-tree. Do not edit by hand.\n\
+       lockfile 1\n\
+       unit 1 0\n  dep 3 1\n  attr \"note\" \"a \\\"quoted\\\"\\n\\\\ value\"\n  attr \"size\" -4\n\
+       unit 2 0\n  dep 1 0\n  dep 3 1\n  dep 3 1\n\
+       leaf 3 1\n  attr \"pinned\" true\n"
+    );
+    let read_back = read(&text).unwrap();
+    assert_eq!(lockfile(&read_back), text);
+    assert_eq!(*read_back.inner, *tree.inner);
+    assert_eq!(read_back.attr((1, 0), "note"), tree.attr((1, 0), "note"));
+  }
+
+  #[test]
+  fn rejects_malformed_lockfiles() {
+    let error = |text: &str| read(text).unwrap_err().to_string();
+    assert!(error("unit 1 0\n").contains("expected the `lockfile` version line"));
+    assert!(error("lockfile 2\n").contains("unsupported lockfile version `2`"));
+    assert!(error("# empty\n").contains("missing the `lockfile` version line"));
+    assert!(error("lockfile 1\n  dep 1 0\n").contains("`dep` before any `unit`"));
+    assert!(error("lockfile 1\nleaf 1 0\n  dep 2 0\n").contains("`dep` under a `leaf`"));
+    assert!(error("lockfile 1\nunit 1\n").contains("expected `unit <id> <version>`"));
+    assert!(error("lockfile 1\nunit 1 0\n  attr \"k\" maybe\n").contains("expected a string, integer or boolean"));
+    assert!(error("lockfile 1\nunit 1 0\n  dep 2 0\nunit 2 0\n  dep 1 0\n").contains("recurses"));
+    assert!(error("lockfile 1\nfrobnicate\n").contains("unknown entry"));
+  }
+}
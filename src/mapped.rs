@@ -0,0 +1,416 @@
+use std::{
+  collections::BTreeSet,
+  fs::File,
+  io::{self, BufWriter, Read, Write},
+  path::Path,
+};
+
+use crate::{DepId, DepMap, DepTree};
+
+const MAGIC: &[u8; 8] = b"DEPTREE\0";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 32;
+const UNIT_LEN: usize = 24;
+const EDGE_LEN: usize = 16;
+
+/// Writes `tree` in the snapshot format read by `MappedTree`: a header with
+/// the magic bytes, format version and unit and edge counts, then the
+/// declared units in `DepId` order with the index of their first edge, then
+/// every edge target. All integers are little-endian `u64`s except the
+/// `u32` version and its padding.
+fn encode<W: Write>(tree: &DepTree, writer: &mut W) -> io::Result<()> {
+  let edges = tree.inner.values().map(|deps| deps.len() as u64).sum::<u64>();
+  writer.write_all(MAGIC)?;
+  writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+  writer.write_all(&0u32.to_le_bytes())?;
+  writer.write_all(&(tree.inner.len() as u64).to_le_bytes())?;
+  writer.write_all(&edges.to_le_bytes())?;
+  let mut first = 0u64;
+  for (&(id, version), deps) in tree.inner.iter() {
+    for value in [id, version as u64, first] {
+      writer.write_all(&value.to_le_bytes())?;
+    }
+    first += deps.len() as u64;
+  }
+  for deps in tree.inner.values() {
+    for &(id, version) in deps.iter() {
+      writer.write_all(&id.to_le_bytes())?;
+      writer.write_all(&(version as u64).to_le_bytes())?;
+    }
+  }
+  Ok(())
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+  u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Checks the header and table bounds of a snapshot, returning its unit and
+/// edge counts.
+fn parse_header(bytes: &[u8]) -> io::Result<(usize, usize)> {
+  if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+    return Err(invalid("not a dep-tree snapshot"));
+  }
+  let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+  if version != FORMAT_VERSION {
+    return Err(invalid(&format!("unsupported snapshot format version {version}")));
+  }
+  let units = usize::try_from(read_u64(bytes, 16)).map_err(|_| invalid("unit count overflows"))?;
+  let edges = usize::try_from(read_u64(bytes, 24)).map_err(|_| invalid("edge count overflows"))?;
+  let expected = units
+    .checked_mul(UNIT_LEN)
+    .zip(edges.checked_mul(EDGE_LEN))
+    .and_then(|(units, edges)| units.checked_add(edges)?.checked_add(HEADER_LEN));
+  if expected != Some(bytes.len()) {
+    return Err(invalid("snapshot length does not match its header"));
+  }
+  let mut previous = None;
+  for index in 0..units {
+    let offset = HEADER_LEN + index * UNIT_LEN;
+    let unit = (read_u64(bytes, offset), read_u64(bytes, offset + 8));
+    let first = read_u64(bytes, offset + 16);
+    if previous.is_some_and(|(previous_unit, previous_first)| previous_unit >= unit || previous_first > first)
+      || first > edges as u64
+    {
+      return Err(invalid("snapshot unit table is out of order"));
+    }
+    previous = Some((unit, first));
+  }
+  Ok((units, edges))
+}
+
+/// Reads back everything `encode` wrote.
+fn decode(bytes: &[u8]) -> io::Result<DepMap> {
+  let (units, edges) = parse_header(bytes)?;
+  let unit = |offset: usize| (read_u64(bytes, offset), read_u64(bytes, offset + 8) as usize);
+  let edge_base = HEADER_LEN + units * UNIT_LEN;
+  let mut map = DepMap::new();
+  for index in 0..units {
+    let offset = HEADER_LEN + index * UNIT_LEN;
+    let first = read_u64(bytes, offset + 16) as usize;
+    let end = if index + 1 < units { read_u64(bytes, offset + UNIT_LEN + 16) as usize } else { edges };
+    map.insert(unit(offset), (first..end).map(|edge| unit(edge_base + edge * EDGE_LEN)).collect());
+  }
+  Ok(map)
+}
+
+/// Snapshot bytes, mapped where the `mmap` feature and platform allow it.
+enum Bytes {
+  #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+  Mapped(sys::Mapping),
+  #[cfg_attr(all(feature = "mmap", unix, target_pointer_width = "64"), allow(dead_code))]
+  Heap(Vec<u8>),
+}
+
+impl std::ops::Deref for Bytes {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    match self {
+      #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+      Self::Mapped(mapping) => mapping.as_slice(),
+      Self::Heap(bytes) => bytes,
+    }
+  }
+}
+
+/// Bare `mmap(2)` binding. Limited to 64-bit unix, where `off_t` is an `i64`
+/// on every libc, since neither `libc` nor `memmap2` is a dependency.
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+mod sys {
+  use std::{
+    fs::File,
+    io,
+    os::{
+      raw::{c_int, c_void},
+      unix::io::AsRawFd,
+    },
+  };
+
+  const PROT_READ: c_int = 1;
+  const MAP_PRIVATE: c_int = 2;
+
+  extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+  }
+
+  /// Read-only private mapping of a whole file. The raw pointer keeps it
+  /// `!Send` and `!Sync`, like the `Rc`-based `DepTree`.
+  pub(super) struct Mapping {
+    ptr: *mut c_void,
+    len: usize,
+  }
+
+  impl Mapping {
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the mapping lives.
+    pub(super) unsafe fn new(file: &File, len: usize) -> io::Result<Self> {
+      // SAFETY: a fresh read-only mapping of `len > 0` bytes at an address of
+      // the kernel's choosing, checked for `MAP_FAILED` below.
+      let ptr = unsafe { mmap(std::ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0) };
+      if ptr as isize == -1 {
+        return Err(io::Error::last_os_error());
+      }
+      Ok(Self { ptr, len })
+    }
+
+    pub(super) fn as_slice(&self) -> &[u8] {
+      // SAFETY: the mapping stays valid and readable until `drop`, and the
+      // caller of `new` promised the file underneath doesn't change.
+      unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+  }
+
+  impl Drop for Mapping {
+    fn drop(&mut self) {
+      // SAFETY: `ptr` and `len` are exactly what `mmap` returned.
+      unsafe {
+        munmap(self.ptr, self.len);
+      }
+    }
+  }
+}
+
+/// Read-only tree served straight from a memory-mapped snapshot file, see
+/// `DepTree::save_mmap`. Nothing is decoded: lookups binary-search the unit
+/// table inside the mapping, so the heap only holds the counts. Only
+/// `direct`, `dependencies_of`, `dependents_of`, `depends_on`, `contains` and
+/// `units` are offered; call `to_tree` for the full query API.
+///
+/// The file is mapped with the `mmap` feature on 64-bit unix, and read onto
+/// the heap everywhere else.
+pub struct MappedTree {
+  bytes: Bytes,
+  units: usize,
+  edges: usize,
+}
+
+impl std::fmt::Debug for MappedTree {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("MappedTree").field("units", &self.units).field("edges", &self.edges).finish()
+  }
+}
+
+impl MappedTree {
+  /// Maps the snapshot at `path`, checking its header and unit table.
+  ///
+  /// # Safety
+  ///
+  /// The file must not be modified or truncated while the tree is open:
+  /// the mapping would change under shared references, or fault on access.
+  pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    let bytes = {
+      let file = File::open(path)?;
+      let len = usize::try_from(file.metadata()?.len()).map_err(|_| invalid("snapshot too large to map"))?;
+      if len < HEADER_LEN {
+        return Err(invalid("not a dep-tree snapshot"));
+      }
+      // SAFETY: forwarded to our caller.
+      Bytes::Mapped(unsafe { sys::Mapping::new(&file, len)? })
+    };
+    #[cfg(not(all(feature = "mmap", unix, target_pointer_width = "64")))]
+    let bytes = Bytes::Heap(std::fs::read(path)?);
+    let (units, edges) = parse_header(&bytes)?;
+    Ok(Self { bytes, units, edges })
+  }
+
+  /// Number of declared units.
+  pub fn len(&self) -> usize {
+    self.units
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.units == 0
+  }
+
+  pub fn edge_count(&self) -> usize {
+    self.edges
+  }
+
+  fn unit_at(&self, index: usize) -> DepId {
+    let offset = HEADER_LEN + index * UNIT_LEN;
+    let bytes = &*self.bytes;
+    (read_u64(bytes, offset), read_u64(bytes, offset + 8) as usize)
+  }
+
+  fn edges_of(&self, index: usize) -> std::ops::Range<usize> {
+    let bytes = &*self.bytes;
+    let first = |index: usize| {
+      if index < self.units {
+        read_u64(bytes, HEADER_LEN + index * UNIT_LEN + 16) as usize
+      } else {
+        self.edges
+      }
+    };
+    first(index)..first(index + 1)
+  }
+
+  fn edge_at(&self, index: usize) -> DepId {
+    let offset = HEADER_LEN + self.units * UNIT_LEN + index * EDGE_LEN;
+    let bytes = &*self.bytes;
+    (read_u64(bytes, offset), read_u64(bytes, offset + 8) as usize)
+  }
+
+  fn index_of(&self, id: DepId) -> Option<usize> {
+    let (mut low, mut high) = (0, self.units);
+    while low < high {
+      let middle = low + (high - low) / 2;
+      match self.unit_at(middle).cmp(&id) {
+        std::cmp::Ordering::Less => low = middle + 1,
+        std::cmp::Ordering::Greater => high = middle,
+        std::cmp::Ordering::Equal => return Some(middle),
+      }
+    }
+    None
+  }
+
+  /// Declared units in `DepId` order.
+  pub fn units(&self) -> impl Iterator<Item = DepId> + '_ {
+    (0..self.units).map(|index| self.unit_at(index))
+  }
+
+  pub fn contains(&self, id: DepId) -> bool {
+    self.index_of(id).is_some()
+  }
+
+  /// Direct dependencies of `id`, empty for undeclared units.
+  pub fn direct(&self, id: DepId) -> impl Iterator<Item = DepId> + '_ {
+    self.index_of(id).map_or(0..0, |index| self.edges_of(index)).map(|edge| self.edge_at(edge))
+  }
+
+  /// Distinct transitive dependencies of `unit`, in the order first reached.
+  pub fn dependencies_of(&self, unit: DepId) -> Vec<DepId> {
+    let mut seen = BTreeSet::from([unit]);
+    let mut dependencies = Vec::new();
+    let mut stack = vec![unit];
+    while let Some(id) = stack.pop() {
+      for dep in self.direct(id) {
+        if seen.insert(dep) {
+          dependencies.push(dep);
+          stack.push(dep);
+        }
+      }
+    }
+    dependencies
+  }
+
+  /// Direct dependents of `unit`. Scans every edge.
+  pub fn dependents_of(&self, unit: DepId) -> Vec<DepId> {
+    (0..self.units)
+      .filter(|&index| self.edges_of(index).any(|edge| self.edge_at(edge) == unit))
+      .map(|index| self.unit_at(index))
+      .collect()
+  }
+
+  pub fn depends_on(&self, a: DepId, b: DepId) -> bool {
+    self.dependencies_of(a).contains(&b)
+  }
+
+  /// Decodes the whole snapshot into a `DepTree`.
+  pub fn to_tree(&self) -> io::Result<DepTree> {
+    Ok(DepTree::from_map(&decode(&self.bytes)?))
+  }
+}
+
+impl DepTree {
+  /// Writes a versioned binary snapshot of the tree, readable by `load` and
+  /// `open_mmap`. Attributes and edge data are not part of the snapshot.
+  pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    encode(self, &mut writer)?;
+    writer.flush()
+  }
+
+  /// Reads a snapshot written by `save` back onto the heap. Snapshots of
+  /// another format version are rejected with `InvalidData`.
+  pub fn load<R: Read>(mut reader: R) -> io::Result<Self> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(Self::from_map(&decode(&bytes)?))
+  }
+
+  /// Writes the tree to `path` in the snapshot format `open_mmap` maps.
+  pub fn save_mmap(&self, path: impl AsRef<Path>) -> io::Result<()> {
+    self.save(BufWriter::new(File::create(path)?))
+  }
+
+  /// Maps a snapshot written by `save_mmap` without decoding it, see
+  /// `MappedTree`.
+  ///
+  /// # Safety
+  ///
+  /// As for `MappedTree::open`.
+  pub unsafe fn open_mmap(path: impl AsRef<Path>) -> io::Result<MappedTree> {
+    // SAFETY: forwarded to our caller.
+    unsafe { MappedTree::open(path) }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::DepTreeBuilder;
+
+  fn tree() -> DepTree {
+    let mut builder = DepTreeBuilder::new();
+    builder.with_dep((1, 0), vec![(2, 0), (3, 0)]);
+    builder.with_dep((2, 0), vec![(3, 0)]);
+    builder.with_dep((5, 1), Vec::new());
+    *builder.build().unwrap()
+  }
+
+  fn snapshot(tree: &DepTree) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    tree.save(&mut bytes).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn save_and_load_round_trip() {
+    let tree = tree();
+    let bytes = snapshot(&tree);
+    assert_eq!(bytes.len(), HEADER_LEN + 3 * UNIT_LEN + 3 * EDGE_LEN);
+    assert_eq!(&bytes[..8], MAGIC);
+    assert_eq!(*DepTree::load(&bytes[..]).unwrap().inner, *tree.inner);
+  }
+
+  #[test]
+  fn rejects_corrupt_snapshots() {
+    let bytes = snapshot(&tree());
+    let error = |bytes: &[u8]| DepTree::load(bytes).unwrap_err();
+    assert_eq!(error(b"DEPTREE").to_string(), "not a dep-tree snapshot");
+    let mut versioned = bytes.clone();
+    versioned[8] = 2;
+    assert_eq!(error(&versioned).to_string(), "unsupported snapshot format version 2");
+    assert_eq!(error(&bytes[..bytes.len() - 1]).to_string(), "snapshot length does not match its header");
+    let mut unordered = bytes.clone();
+    unordered[HEADER_LEN..HEADER_LEN + 8].copy_from_slice(&9u64.to_le_bytes());
+    assert_eq!(error(&unordered).to_string(), "snapshot unit table is out of order");
+    assert_eq!(error(&unordered).kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn mapped_tree_answers_without_decoding() {
+    let path = std::env::temp_dir().join(format!("dep-tree-mapped-{}.snapshot", std::process::id()));
+    tree().save_mmap(&path).unwrap();
+    // SAFETY: nothing else touches the file while it is open.
+    let mapped = unsafe { DepTree::open_mmap(&path) }.unwrap();
+    assert_eq!((mapped.len(), mapped.edge_count()), (3, 3));
+    assert_eq!(mapped.units().collect::<Vec<_>>(), [(1, 0), (2, 0), (5, 1)]);
+    assert!(mapped.contains((5, 1)) && !mapped.contains((3, 0)));
+    assert_eq!(mapped.direct((1, 0)).collect::<Vec<_>>(), [(2, 0), (3, 0)]);
+    assert_eq!(mapped.direct((3, 0)).count(), 0);
+    assert_eq!(mapped.dependencies_of((1, 0)), [(2, 0), (3, 0)]);
+    assert_eq!(mapped.dependents_of((3, 0)), [(1, 0), (2, 0)]);
+    assert!(mapped.depends_on((1, 0), (3, 0)) && !mapped.depends_on((3, 0), (1, 0)));
+    assert_eq!(*mapped.to_tree().unwrap().inner, *tree().inner);
+    drop(mapped);
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{DepId, DepTree};
+
+/// Bit-packed square matrix over `nodes`, where entry `(i, j)` is set when
+/// `nodes[i]` depends directly on `nodes[j]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdjacencyMatrix {
+  nodes: Vec<DepId>,
+  index: BTreeMap<DepId, usize>,
+  words: usize,
+  bits: Vec<u64>,
+}
+
+impl AdjacencyMatrix {
+  fn new(nodes: Vec<DepId>) -> Self {
+    let index = nodes.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    let words = nodes.len().div_ceil(64);
+    Self {
+      bits: vec![0; words * nodes.len()],
+      nodes,
+      index,
+      words,
+    }
+  }
+
+  fn set(&mut self, row: usize, column: usize) {
+    self.bits[row * self.words + column / 64] |= 1 << (column % 64);
+  }
+
+  /// Sets every entry of `row` that is set in row `from`.
+  fn union_row(&mut self, row: usize, from: usize) {
+    for word in 0..self.words {
+      self.bits[row * self.words + word] |= self.bits[from * self.words + word];
+    }
+  }
+
+  /// Row and column order.
+  pub fn nodes(&self) -> &[DepId] {
+    &self.nodes
+  }
+
+  pub fn index_of(&self, id: DepId) -> Option<usize> {
+    self.index.get(&id).copied()
+  }
+
+  pub fn get(&self, row: usize, column: usize) -> bool {
+    self.bits[row * self.words + column / 64] & (1 << (column % 64)) != 0
+  }
+
+  /// Packed row `row`: bit `j % 64` of word `j / 64` is entry `(row, j)`.
+  pub fn row_words(&self, row: usize) -> &[u64] {
+    &self.bits[row * self.words..(row + 1) * self.words]
+  }
+
+  /// Row-major `0`/`1` matrix, ready to hand to numeric code.
+  pub fn to_dense(&self) -> Vec<Vec<u8>> {
+    (0..self.nodes.len())
+      .map(|row| (0..self.nodes.len()).map(|column| self.get(row, column) as u8).collect())
+      .collect()
+  }
+}
+
+/// All-pairs reachability over every unit, answering `reaches(a, b)` with a
+/// single bit lookup. Takes `n^2 / 8` bytes, so it is meant for graphs of up to
+/// some tens of thousands of units.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reachability(AdjacencyMatrix);
+
+impl Reachability {
+  /// Whether `a` depends on `b`, directly or transitively.
+  pub fn reaches(&self, a: DepId, b: DepId) -> bool {
+    match (self.0.index_of(a), self.0.index_of(b)) {
+      (Some(row), Some(column)) => self.0.get(row, column),
+      _ => false,
+    }
+  }
+
+  /// Everything `a` depends on, in `DepId` order.
+  pub fn reachable_from(&self, a: DepId) -> impl Iterator<Item = DepId> + '_ {
+    let row = self.0.index_of(a);
+    self.0
+      .nodes()
+      .iter()
+      .enumerate()
+      .filter(move |&(column, _)| row.is_some_and(|row| self.0.get(row, column)))
+      .map(|(_, &id)| id)
+  }
+
+  /// The underlying matrix, entry `(i, j)` set when `nodes[i]` reaches `nodes[j]`.
+  pub fn matrix(&self) -> &AdjacencyMatrix {
+    &self.0
+  }
+}
+
+impl DepTree {
+  /// Transitive closure of `to_adjacency_matrix`, built in one pass over a
+  /// topological order.
+  pub fn reachability(&self) -> Reachability {
+    let mut matrix = self.to_adjacency_matrix();
+    let mut order = Vec::new();
+    let mut visited = BTreeSet::new();
+    for &id in matrix.nodes() {
+      self.post_order(id, &mut visited, &mut order);
+    }
+    for id in order {
+      let row = matrix.index[&id];
+      for dep in self.direct(id) {
+        matrix.union_row(row, matrix.index[dep]);
+      }
+    }
+    Reachability(matrix)
+  }
+
+  /// Adjacency matrix over every unit in `DepId` order.
+  pub fn to_adjacency_matrix(&self) -> AdjacencyMatrix {
+    let mut matrix = AdjacencyMatrix::new(self.reverse_map().into_keys().collect());
+    for (unit, deps) in self.inner.iter() {
+      let row = matrix.index[unit];
+      for dep in deps.iter() {
+        let column = matrix.index[dep];
+        matrix.set(row, column);
+      }
+    }
+    matrix
+  }
+}
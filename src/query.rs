@@ -0,0 +1,429 @@
+use std::{
+  cell::OnceCell,
+  collections::BTreeSet,
+  fmt,
+  rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::{bfs, DepId, DepMap, DepTree};
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum QueryError {
+  #[error("query error at {position}: {message}")]
+  Syntax { position: usize, message: String },
+}
+
+#[derive(Clone)]
+struct Predicate(Rc<dyn Fn(DepId) -> bool>);
+
+impl fmt::Debug for Predicate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("Predicate")
+  }
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+  All,
+  Unit(DepId),
+  Deps(DepId, Option<usize>),
+  Rdeps(DepId, Option<usize>),
+  Not(Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Or(Box<Expr>, Box<Expr>),
+  Minus(Box<Expr>, Box<Expr>),
+  Filter(Box<Expr>, Predicate),
+}
+
+/// Set of units described by combining closures, evaluated against a tree
+/// with `eval`. `DepTree::query` parses the textual form of the same queries.
+#[derive(Clone, Debug)]
+pub struct Query(Expr);
+
+impl Query {
+  pub fn parse(query: &str) -> Result<Self, QueryError> {
+    Parser::new(query).parse().map(Self)
+  }
+
+  pub fn all() -> Self {
+    Self(Expr::All)
+  }
+
+  pub fn unit(unit: DepId) -> Self {
+    Self(Expr::Unit(unit))
+  }
+
+  /// Transitive dependencies of `unit`, excluding itself.
+  pub fn deps(unit: DepId) -> Self {
+    Self(Expr::Deps(unit, None))
+  }
+
+  /// Dependencies at most `depth` edges away from `unit`.
+  pub fn deps_within(unit: DepId, depth: usize) -> Self {
+    Self(Expr::Deps(unit, Some(depth)))
+  }
+
+  /// Transitive dependents of `unit`, excluding itself.
+  pub fn rdeps(unit: DepId) -> Self {
+    Self(Expr::Rdeps(unit, None))
+  }
+
+  /// Dependents at most `depth` edges away from `unit`.
+  pub fn rdeps_within(unit: DepId, depth: usize) -> Self {
+    Self(Expr::Rdeps(unit, Some(depth)))
+  }
+
+  pub fn union(self, other: Query) -> Self {
+    Self(Expr::Or(Box::new(self.0), Box::new(other.0)))
+  }
+
+  pub fn intersection(self, other: Query) -> Self {
+    Self(Expr::And(Box::new(self.0), Box::new(other.0)))
+  }
+
+  pub fn difference(self, other: Query) -> Self {
+    Self(Expr::Minus(Box::new(self.0), Box::new(other.0)))
+  }
+
+  /// Every unit of the tree not in this set.
+  pub fn complement(self) -> Self {
+    Self(Expr::Not(Box::new(self.0)))
+  }
+
+  pub fn filter(self, predicate: impl Fn(DepId) -> bool + 'static) -> Self {
+    Self(Expr::Filter(Box::new(self.0), Predicate(Rc::new(predicate))))
+  }
+
+  pub fn eval(&self, tree: &DepTree) -> BTreeSet<DepId> {
+    Evaluator::new(tree).eval(&self.0)
+  }
+}
+
+impl DepTree {
+  /// Evaluates a query over the tree's units:
+  ///
+  /// - `(1, 0)` is the unit itself and `all` is every unit;
+  /// - `deps((1, 0))` and `rdeps((1, 0))` are its transitive dependencies and
+  ///   dependents, optionally bounded with `depth<=N` (or `depth<N`);
+  /// - `a & b`, `a | b`, `a - b` and `!a` combine sets, `&` and `-` binding
+  ///   tighter than `|`, with parentheses for grouping.
+  ///
+  /// For example `deps((1, 0)) & !deps((2, 0))` or `rdeps((3, 0)) depth<=2`.
+  pub fn query(&self, query: &str) -> Result<BTreeSet<DepId>, QueryError> {
+    Ok(Query::parse(query)?.eval(self))
+  }
+}
+
+struct Evaluator<'a> {
+  tree: &'a DepTree,
+  dependents: OnceCell<DepMap>,
+}
+
+impl<'a> Evaluator<'a> {
+  fn new(tree: &'a DepTree) -> Self {
+    Self {
+      tree,
+      dependents: OnceCell::new(),
+    }
+  }
+
+  /// Built on first use, as only `all`, `!` and `rdeps` need it.
+  fn dependents(&self) -> &DepMap {
+    self.dependents.get_or_init(|| self.tree.reverse_map())
+  }
+
+  fn eval(&self, expr: &Expr) -> BTreeSet<DepId> {
+    match expr {
+      Expr::All => self.dependents().keys().copied().collect(),
+      Expr::Unit(unit) => BTreeSet::from([*unit]),
+      Expr::Deps(unit, depth) => {
+        bfs(*unit, *depth, |id| self.tree.direct(id).iter().copied()).into_keys().collect()
+      }
+      Expr::Rdeps(unit, depth) => {
+        let dependents = self.dependents();
+        let next = |id| dependents.get(&id).into_iter().flatten().copied();
+        bfs(*unit, *depth, next).into_keys().collect()
+      }
+      Expr::Not(inner) => {
+        let inner = self.eval(inner);
+        self.dependents().keys().filter(|id| !inner.contains(id)).copied().collect()
+      }
+      Expr::Filter(inner, predicate) => {
+        self.eval(inner).into_iter().filter(|&id| (predicate.0)(id)).collect()
+      }
+      Expr::And(a, b) => &self.eval(a) & &self.eval(b),
+      Expr::Or(a, b) => &self.eval(a) | &self.eval(b),
+      Expr::Minus(a, b) => &self.eval(a) - &self.eval(b),
+    }
+  }
+}
+
+/// Deepest `!` and parenthesis nesting `Query::parse` accepts.
+const MAX_DEPTH: usize = 128;
+
+/// Joins `operands` with `join` as a balanced tree, so evaluating a long
+/// chain like `a | b | c | ..` recurses only logarithmically deep.
+fn balanced(mut operands: Vec<Expr>, join: fn(Box<Expr>, Box<Expr>) -> Expr) -> Expr {
+  while operands.len() > 1 {
+    let mut joined = Vec::with_capacity(operands.len().div_ceil(2));
+    let mut operands_iter = operands.into_iter();
+    while let Some(a) = operands_iter.next() {
+      joined.push(match operands_iter.next() {
+        Some(b) => join(Box::new(a), Box::new(b)),
+        None => a,
+      });
+    }
+    operands = joined;
+  }
+  operands.pop().unwrap()
+}
+
+struct Parser<'a> {
+  text: &'a str,
+  position: usize,
+  depth: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn new(text: &'a str) -> Self {
+    Self { text, position: 0, depth: 0 }
+  }
+
+  fn error<T>(&self, message: &str) -> Result<T, QueryError> {
+    Err(QueryError::Syntax {
+      position: self.position,
+      message: message.to_owned(),
+    })
+  }
+
+  fn rest(&self) -> &'a str {
+    &self.text[self.position..]
+  }
+
+  fn skip_whitespace(&mut self) {
+    let rest = self.rest();
+    self.position += rest.len() - rest.trim_start().len();
+  }
+
+  fn eat(&mut self, token: &str) -> bool {
+    self.skip_whitespace();
+    if self.rest().starts_with(token) {
+      self.position += token.len();
+      true
+    } else {
+      false
+    }
+  }
+
+  fn parse(mut self) -> Result<Expr, QueryError> {
+    let expr = self.or()?;
+    self.skip_whitespace();
+    if !self.rest().is_empty() {
+      return self.error("unexpected input");
+    }
+    Ok(expr)
+  }
+
+  fn or(&mut self) -> Result<Expr, QueryError> {
+    let mut operands = vec![self.and()?];
+    while self.eat("|") {
+      operands.push(self.and()?);
+    }
+    Ok(balanced(operands, Expr::Or))
+  }
+
+  /// A chain of `&` and `-`, evaluated left to right, is the intersection of
+  /// the `&` operands minus the union of the `-` ones.
+  fn and(&mut self) -> Result<Expr, QueryError> {
+    let mut kept = vec![self.unary()?];
+    let mut removed = Vec::new();
+    loop {
+      if self.eat("&") {
+        kept.push(self.unary()?);
+      } else if self.eat("-") {
+        removed.push(self.unary()?);
+      } else {
+        break;
+      }
+    }
+    let expr = balanced(kept, Expr::And);
+    if removed.is_empty() {
+      return Ok(expr);
+    }
+    Ok(Expr::Minus(Box::new(expr), Box::new(balanced(removed, Expr::Or))))
+  }
+
+  /// Parses a `!` or parenthesised operand, refusing to go deeper than
+  /// `MAX_DEPTH` so hostile input can't exhaust the stack.
+  fn nested(&mut self, parse: fn(&mut Self) -> Result<Expr, QueryError>) -> Result<Expr, QueryError> {
+    if self.depth == MAX_DEPTH {
+      return self.error("nesting too deep");
+    }
+    self.depth += 1;
+    let expr = parse(self);
+    self.depth -= 1;
+    expr
+  }
+
+  fn unary(&mut self) -> Result<Expr, QueryError> {
+    if self.eat("!") {
+      return self.nested(Self::unary).map(|expr| Expr::Not(Box::new(expr)));
+    }
+    self.skip_whitespace();
+    if self.rest().starts_with('(') {
+      if self.looks_like_unit() {
+        return self.unit().map(Expr::Unit);
+      }
+      self.position += 1;
+      let expr = self.nested(Self::or)?;
+      if !self.eat(")") {
+        return self.error("expected `)`");
+      }
+      return Ok(expr);
+    }
+    if self.eat("all") {
+      return Ok(Expr::All);
+    }
+    let reverse = if self.eat("rdeps") {
+      true
+    } else if self.eat("deps") {
+      false
+    } else {
+      return self.error("expected a unit, `all`, `deps(..)` or `rdeps(..)`");
+    };
+    if !self.eat("(") {
+      return self.error("expected `(`");
+    }
+    let unit = self.unit()?;
+    if !self.eat(")") {
+      return self.error("expected `)`");
+    }
+    let depth = self.depth()?;
+    Ok(if reverse {
+      Expr::Rdeps(unit, depth)
+    } else {
+      Expr::Deps(unit, depth)
+    })
+  }
+
+  fn depth(&mut self) -> Result<Option<usize>, QueryError> {
+    if !self.eat("depth") {
+      return Ok(None);
+    }
+    let inclusive = if self.eat("<=") {
+      true
+    } else if self.eat("<") {
+      false
+    } else {
+      return self.error("expected `<=` or `<`");
+    };
+    let depth = self.integer()? as usize;
+    Ok(Some(if inclusive { depth } else { depth.saturating_sub(1) }))
+  }
+
+  /// Whether the `(` at the cursor opens a `(id, version)` literal rather than
+  /// a group.
+  fn looks_like_unit(&self) -> bool {
+    let inner = self.rest()[1..].trim_start();
+    let digits = inner.len() - inner.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    digits > 0 && inner[digits..].trim_start().starts_with(',')
+  }
+
+  fn unit(&mut self) -> Result<DepId, QueryError> {
+    if !self.eat("(") {
+      return self.error("expected `(id, version)`");
+    }
+    let id = self.integer()?;
+    if !self.eat(",") {
+      return self.error("expected `,`");
+    }
+    let version = self.integer()? as usize;
+    if !self.eat(")") {
+      return self.error("expected `)`");
+    }
+    Ok((id, version))
+  }
+
+  fn integer(&mut self) -> Result<u64, QueryError> {
+    self.skip_whitespace();
+    let rest = self.rest();
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    match rest[..digits].parse() {
+      Ok(value) => {
+        self.position += digits;
+        Ok(value)
+      }
+      Err(_) => self.error("expected an integer"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::DepTreeBuilder;
+
+  /// `(1, 0) -> (2, 0) -> (3, 0) <- (4, 0)`.
+  fn tree() -> DepTree {
+    let mut builder = DepTreeBuilder::new();
+    builder.with_dep((1, 0), vec![(2, 0)]);
+    builder.with_dep((2, 0), vec![(3, 0)]);
+    builder.with_dep((4, 0), vec![(3, 0)]);
+    *builder.build().unwrap()
+  }
+
+  fn query(query: &str) -> Vec<DepId> {
+    tree().query(query).unwrap().into_iter().collect()
+  }
+
+  #[test]
+  fn evaluates_terms() {
+    assert_eq!(query("all"), [(1, 0), (2, 0), (3, 0), (4, 0)]);
+    assert_eq!(query("( 4 , 0 )"), [(4, 0)]);
+    assert_eq!(query("deps((1, 0))"), [(2, 0), (3, 0)]);
+    assert_eq!(query("deps((1, 0)) depth<=1"), [(2, 0)]);
+    assert_eq!(query("deps((1, 0)) depth<2"), [(2, 0)]);
+    assert_eq!(query("rdeps((3, 0))"), [(1, 0), (2, 0), (4, 0)]);
+  }
+
+  #[test]
+  fn combines_sets_with_precedence() {
+    assert_eq!(query("!deps((1, 0))"), [(1, 0), (4, 0)]);
+    assert_eq!(query("rdeps((3, 0)) & deps((1, 0))"), [(2, 0)]);
+    assert_eq!(query("(1, 0) | (4, 0) - (4, 0)"), [(1, 0)]);
+    assert_eq!(query("((1, 0) | (4, 0)) - (4, 0)"), [(1, 0)]);
+    assert_eq!(query("all - (1, 0) & rdeps((3, 0)) - (2, 0)"), [(4, 0)]);
+  }
+
+  #[test]
+  fn long_chains_evaluate() {
+    let chain = vec!["(1, 0)"; 10_000].join(" | ");
+    assert_eq!(query(&chain), [(1, 0)]);
+    let chain = format!("all{}", " & all".repeat(10_000));
+    assert_eq!(query(&chain).len(), 4);
+  }
+
+  #[test]
+  fn reports_syntax_errors() {
+    let error = |query: &str| match tree().query(query).unwrap_err() {
+      QueryError::Syntax { position, message } => (position, message),
+    };
+    assert_eq!(error("deps((1, 0)"), (11, "expected `)`".to_owned()));
+    assert_eq!(error("all all"), (4, "unexpected input".to_owned()));
+    assert_eq!(error("deps((1, 0)) depth=1"), (18, "expected `<=` or `<`".to_owned()));
+    assert_eq!(error("(1)").1, "expected a unit, `all`, `deps(..)` or `rdeps(..)`");
+    let deep = "(".repeat(MAX_DEPTH + 1) + "all" + &")".repeat(MAX_DEPTH + 1);
+    assert_eq!(error(&deep).1, "nesting too deep");
+    assert!(tree().query(&("!".repeat(MAX_DEPTH) + "all")).is_ok());
+  }
+
+  #[test]
+  fn builds_queries_in_code() {
+    let query = Query::deps((1, 0)).union(Query::unit((4, 0))).difference(Query::deps_within((1, 0), 1));
+    assert_eq!(query.eval(&tree()), BTreeSet::from([(3, 0), (4, 0)]));
+    let query = Query::all().filter(|(id, _)| id % 2 == 0).complement();
+    assert_eq!(query.eval(&tree()), BTreeSet::from([(1, 0), (3, 0)]));
+  }
+}
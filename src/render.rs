@@ -0,0 +1,100 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{label, DepId, DepTree};
+
+#[cfg(feature = "color")]
+const DEPTH_COLORS: [u8; 6] = [36, 32, 33, 35, 34, 31];
+
+impl DepTree {
+  /// Renders `unit` and its dependencies like `cargo tree`. Subtrees that were
+  /// already printed are shown once and then marked with `(*)`.
+  pub fn render_tree(&self, unit: DepId) -> String {
+    self.render_tree_with(unit, &|unit, _| label(unit))
+  }
+
+  /// `render_tree` with every unit colored by its depth, or plain when
+  /// `NO_COLOR` is set.
+  #[cfg(feature = "color")]
+  pub fn render_tree_colored(&self, unit: DepId) -> String {
+    if !use_color() {
+      return self.render_tree(unit);
+    }
+    self.render_tree_with(unit, &|unit, depth| {
+      paint(&label(unit), DEPTH_COLORS[depth % DEPTH_COLORS.len()])
+    })
+  }
+
+  /// One `unit count` line per entry of a ranking such as `most_dependents`.
+  pub fn render_ranking(ranking: &[(DepId, usize)]) -> String {
+    ranking
+      .iter()
+      .map(|&(unit, count)| format!("{} {count}\n", label(unit)))
+      .collect()
+  }
+
+  /// `render_ranking` colored by count: red for the top third of the range,
+  /// yellow for the middle and green for the rest. Plain when `NO_COLOR` is
+  /// set.
+  #[cfg(feature = "color")]
+  pub fn render_ranking_colored(ranking: &[(DepId, usize)]) -> String {
+    if !use_color() {
+      return Self::render_ranking(ranking);
+    }
+    let max = ranking.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    ranking
+      .iter()
+      .map(|&(unit, count)| {
+        let color = match count * 3 {
+          scaled if max > 0 && scaled > max * 2 => 31,
+          scaled if max > 0 && scaled > max => 33,
+          _ => 32,
+        };
+        format!("{}\n", paint(&format!("{} {count}", label(unit)), color))
+      })
+      .collect()
+  }
+
+  fn render_tree_with(&self, unit: DepId, style: &dyn Fn(DepId, usize) -> String) -> String {
+    let mut out = String::new();
+    writeln!(out, "{}", style(unit, 0)).unwrap();
+    let mut printed = BTreeSet::from([unit]);
+    self.render_children(unit, "", 1, style, &mut printed, &mut out);
+    out
+  }
+
+  fn render_children(
+    &self,
+    unit: DepId,
+    prefix: &str,
+    depth: usize,
+    style: &dyn Fn(DepId, usize) -> String,
+    printed: &mut BTreeSet<DepId>,
+    out: &mut String,
+  ) {
+    let Some(deps) = self.inner.get(&unit) else {
+      return;
+    };
+    for (i, &dep) in deps.iter().enumerate() {
+      let last = i + 1 == deps.len();
+      let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+      let expandable = self.inner.get(&dep).is_some_and(|deps| !deps.is_empty());
+      if !printed.insert(dep) && expandable {
+        writeln!(out, "{prefix}{branch}{} (*)", style(dep, depth)).unwrap();
+        continue;
+      }
+      writeln!(out, "{prefix}{branch}{}", style(dep, depth)).unwrap();
+      let prefix = format!("{prefix}{indent}");
+      self.render_children(dep, &prefix, depth + 1, style, printed, out);
+    }
+  }
+}
+
+#[cfg(feature = "color")]
+fn use_color() -> bool {
+  std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+#[cfg(feature = "color")]
+fn paint(text: &str, color: u8) -> String {
+  format!("\x1b[{color}m{text}\x1b[0m")
+}
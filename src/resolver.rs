@@ -0,0 +1,106 @@
+use std::{
+  collections::{BTreeSet, VecDeque},
+  future::{poll_fn, Future},
+  pin::{pin, Pin},
+  sync::Arc,
+  task::{Context, Poll, Wake, Waker},
+  thread::{self, Thread},
+};
+
+use crate::{DepId, DepMap, DepTreeBuilder};
+
+/// Discovers a graph from its roots by asking `deps_of` for the dependencies
+/// of every unit it reaches, breadth-first, with at most `concurrency`
+/// requests in flight. The futures can come from any runtime; `resolve`
+/// awaits them itself, and `resolve_blocking` needs no runtime at all as long
+/// as the futures don't rely on one.
+pub struct Resolver<F> {
+  deps_of: F,
+  concurrency: usize,
+}
+
+impl<F, Fut> Resolver<F>
+where
+  F: FnMut(DepId) -> Fut,
+  Fut: Future<Output = Vec<DepId>>,
+{
+  /// Resolver with one request in flight at a time.
+  pub fn new(deps_of: F) -> Self {
+    Self { deps_of, concurrency: 1 }
+  }
+
+  /// Allows up to `limit` requests in flight, at least one.
+  pub fn concurrency(mut self, limit: usize) -> Self {
+    self.concurrency = limit.max(1);
+    self
+  }
+
+  /// Fetches every unit reachable from `roots` once and returns a builder
+  /// holding what was found. Units are requested in the order they were
+  /// first discovered; responses may complete in any order.
+  pub async fn resolve(mut self, roots: impl IntoIterator<Item = DepId>) -> DepTreeBuilder {
+    let mut discovered = BTreeSet::new();
+    let mut queue = roots.into_iter().filter(|&id| discovered.insert(id)).collect::<VecDeque<_>>();
+    let mut in_flight: Vec<(DepId, Pin<Box<Fut>>)> = Vec::new();
+    let mut map = DepMap::new();
+    poll_fn(|cx| loop {
+      while in_flight.len() < self.concurrency {
+        let Some(id) = queue.pop_front() else {
+          break;
+        };
+        in_flight.push((id, Box::pin((self.deps_of)(id))));
+      }
+      let mut progress = false;
+      let mut index = 0;
+      while index < in_flight.len() {
+        let Poll::Ready(deps) = in_flight[index].1.as_mut().poll(cx) else {
+          index += 1;
+          continue;
+        };
+        let (id, _) = in_flight.swap_remove(index);
+        queue.extend(deps.iter().copied().filter(|&dep| discovered.insert(dep)));
+        map.insert(id, deps);
+        progress = true;
+      }
+      if in_flight.is_empty() && queue.is_empty() {
+        return Poll::Ready(());
+      }
+      if !progress {
+        return Poll::Pending;
+      }
+    })
+    .await;
+
+    let mut builder = DepTreeBuilder::new();
+    for (id, deps) in map {
+      builder.with_dep(id, deps);
+    }
+    builder
+  }
+
+  /// Runs `resolve` to completion on the current thread.
+  pub fn resolve_blocking(self, roots: impl IntoIterator<Item = DepId>) -> DepTreeBuilder {
+    block_on(self.resolve(roots))
+  }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+  fn wake(self: Arc<Self>) {
+    self.0.unpark();
+  }
+}
+
+/// Polls `future` on the current thread, parking it until woken.
+fn block_on<T>(future: impl Future<Output = T>) -> T {
+  let mut future = pin!(future);
+  let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+  let mut cx = Context::from_waker(&waker);
+  loop {
+    if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+      return value;
+    }
+    thread::park();
+  }
+}
@@ -0,0 +1,177 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt,
+  rc::Rc,
+};
+
+use crate::{DepId, DepMap, DepTree, DepTreeBuilder, DepTreeBuilderError, DepTreeBuilderResult, Edge, EdgeData};
+
+/// Edge rewrites `DepTreeBuilder::build` applied, see `DepTree::build_report`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildReport {
+  /// Edges `(dependent, declared target, replacement)` redirected by
+  /// `DepTreeBuilder::with_override`, in declaration order per dependent.
+  pub overrides: Vec<(DepId, DepId, DepId)>,
+  /// Declared edges `(dependent, target)` dropped by
+  /// `DepTreeBuilder::with_exclusion`.
+  pub suppressed: Vec<(DepId, DepId)>,
+}
+
+#[derive(Clone)]
+struct Exclusion {
+  parent: Rc<dyn Fn(DepId) -> bool>,
+  dep: DepId,
+}
+
+impl fmt::Debug for Exclusion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Exclusion").field("dep", &self.dep).finish_non_exhaustive()
+  }
+}
+
+/// Edge rewrites `DepTreeBuilder::build` applies before validating.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Rules {
+  /// Units providing each virtual unit.
+  providers: BTreeMap<DepId, BTreeSet<DepId>>,
+  preferred: BTreeMap<DepId, DepId>,
+  overrides: BTreeMap<DepId, DepId>,
+  exclusions: Vec<Exclusion>,
+}
+
+impl Rules {
+  fn is_empty(&self) -> bool {
+    self.providers.is_empty() && self.overrides.is_empty() && self.exclusions.is_empty()
+  }
+
+  /// Provider that edges to the virtual unit `dep` resolve to, if it is one.
+  fn provider(&self, dep: DepId) -> DepTreeBuilderResult<Option<DepId>> {
+    let Some(providers) = self.providers.get(&dep) else {
+      return Ok(None);
+    };
+    if let Some(&preferred) = self.preferred.get(&dep) {
+      return Ok(Some(preferred));
+    }
+    if providers.len() > 1 {
+      return Err(DepTreeBuilderError::AmbiguousProvider(dep, providers.iter().copied().collect()));
+    }
+    Ok(providers.first().copied())
+  }
+
+  /// `map` and `edges` with every rule applied, `None` when there is nothing
+  /// to rewrite. Edges that only coincide because of a rewrite are kept once.
+  /// Fails when an override would redirect edges away from a unit `pinned`
+  /// holds.
+  pub(crate) fn apply(
+    &self,
+    map: &DepMap,
+    edges: &EdgeData,
+    pinned: impl Fn(DepId) -> bool,
+  ) -> DepTreeBuilderResult<Option<(DepMap, EdgeData, BuildReport)>> {
+    if self.is_empty() {
+      return Ok(None);
+    }
+    let mut report = BuildReport::default();
+    let mut moved = BTreeMap::<Edge, DepId>::new();
+    let mut dropped = BTreeSet::<Edge>::new();
+    let mut resolved = DepMap::new();
+    for (&id, deps) in map {
+      let list = resolved.entry(id).or_default();
+      for (index, &declared) in deps.iter().enumerate() {
+        let mut dep = self.provider(declared)?.unwrap_or(declared);
+        let excluded = |rule: &Exclusion| (rule.dep == declared || rule.dep == dep) && (rule.parent)(id);
+        if self.exclusions.iter().any(excluded) {
+          if dropped.insert((id, declared)) {
+            report.suppressed.push((id, declared));
+          }
+          continue;
+        }
+        // The replacement itself may still wrap the unit it replaces.
+        if let Some(&replacement) = self.overrides.get(&dep).filter(|&&replacement| replacement != id) {
+          if pinned(dep) {
+            return Err(DepTreeBuilderError::Pinned(dep));
+          }
+          if !deps[..index].contains(&declared) {
+            report.overrides.push((id, declared, replacement));
+          }
+          dep = replacement;
+        }
+        // Repeats declared as such are left to the builder's options.
+        let rewritten = dep != declared || !deps[..index].contains(&declared);
+        if dep != declared {
+          moved.insert((id, declared), dep);
+        }
+        if !(rewritten && list.contains(&dep)) {
+          list.push(dep);
+        }
+      }
+    }
+    let rename = |edge @ (id, _): Edge| (id, moved.get(&edge).copied().unwrap_or(edge.1));
+    let mut data = EdgeData::default();
+    for (&edge, &count) in edges.counts.iter().filter(|(edge, _)| !dropped.contains(edge)) {
+      data.counts.entry(rename(edge)).or_insert(count);
+    }
+    for (&edge, payload) in edges.payloads.iter().filter(|(edge, _)| !dropped.contains(edge)) {
+      data.payloads.entry(rename(edge)).or_insert_with(|| payload.clone());
+    }
+    for (&edge, groups) in edges.groups.iter().filter(|(edge, _)| !dropped.contains(edge)) {
+      data.groups.entry(rename(edge)).or_default().extend(groups.iter().cloned());
+    }
+    for (&edge, exprs) in edges.targets.iter().filter(|(edge, _)| !dropped.contains(edge)) {
+      data.targets.entry(rename(edge)).or_default().extend(exprs.iter().cloned());
+    }
+    Ok(Some((resolved, data, report)))
+  }
+}
+
+impl DepTreeBuilder {
+  /// Declares that `provider` provides the virtual unit `virtual_id`: when
+  /// building, every edge to `virtual_id` points at its provider instead.
+  /// Building fails if several units provide it and none is preferred with
+  /// `prefer_provider`.
+  pub fn provides(&mut self, provider: DepId, virtual_id: DepId) -> Self {
+    self.rules.try_borrow_mut().unwrap().providers.entry(virtual_id).or_default().insert(provider);
+    self.clone()
+  }
+
+  /// Resolves edges to `virtual_id` to `provider`, even when other units
+  /// provide it as well.
+  pub fn prefer_provider(&mut self, virtual_id: DepId, provider: DepId) -> Self {
+    let mut rules = self.rules.try_borrow_mut().unwrap();
+    rules.providers.entry(virtual_id).or_default().insert(provider);
+    rules.preferred.insert(virtual_id, provider);
+    drop(rules);
+    self.clone()
+  }
+
+  /// Points every edge to `original` at `replacement` when building, the way
+  /// Cargo's `[patch]` or npm's `overrides` do, except edges from
+  /// `replacement` itself. Each redirected edge is listed in
+  /// `DepTree::build_report`. Applies after virtual units are resolved and
+  /// is not chained: an override of `replacement` does not apply as well.
+  /// Building fails with `Pinned` if `original` is pinned and has edges to
+  /// redirect.
+  pub fn with_override(&mut self, original: DepId, replacement: DepId) -> Self {
+    self.rules.try_borrow_mut().unwrap().overrides.insert(original, replacement);
+    self.clone()
+  }
+
+  /// Drops the edges to `excluded_dep` declared by every unit matching
+  /// `parent_pattern` when building, like a Maven `<exclusion>`, e.g.
+  /// `with_exclusion(|(id, _)| id == 3, (7, 0))` for every version of `3`.
+  /// Edges to a virtual unit are dropped when it resolves to `excluded_dep`.
+  /// Each dropped edge is listed in `DepTree::build_report`.
+  pub fn with_exclusion(&mut self, parent_pattern: impl Fn(DepId) -> bool + 'static, excluded_dep: DepId) -> Self {
+    let exclusion = Exclusion { parent: Rc::new(parent_pattern), dep: excluded_dep };
+    self.rules.try_borrow_mut().unwrap().exclusions.push(exclusion);
+    self.clone()
+  }
+}
+
+impl DepTree {
+  /// Rewrites applied when this tree, or the tree it was derived from, was
+  /// built.
+  pub fn build_report(&self) -> &BuildReport {
+    &self.report
+  }
+}
@@ -0,0 +1,180 @@
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, BTreeSet},
+  panic::{self, AssertUnwindSafe},
+  sync::{mpsc, Arc, Mutex},
+  thread::{self, JoinHandle},
+};
+
+use crate::{DepId, DepTree, Scheduler};
+
+type JobResult<E> = (DepId, thread::Result<Result<(), E>>);
+
+/// Outcome of `DepTree::run`. Units depending on a failed unit are skipped.
+#[derive(Debug)]
+pub struct RunReport<E> {
+  pub completed: BTreeSet<DepId>,
+  pub failed: Vec<(DepId, E)>,
+  pub skipped: BTreeSet<DepId>,
+}
+
+impl<E> RunReport<E> {
+  pub fn is_success(&self) -> bool {
+    self.failed.is_empty()
+  }
+
+  pub fn into_result(self) -> Result<BTreeSet<DepId>, Vec<(DepId, E)>> {
+    if self.failed.is_empty() {
+      Ok(self.completed)
+    } else {
+      Err(self.failed)
+    }
+  }
+}
+
+/// Pool the runner submits units to. `join` is called on a job's handle as
+/// soon as its result is in. Only `ThreadExecutor` ships with the crate;
+/// pools such as rayon's plug in through this trait.
+pub trait Executor {
+  type Handle;
+
+  fn spawn<F: FnOnce() + Send + 'static>(&self, job: F) -> Self::Handle;
+
+  fn join(&self, handle: Self::Handle);
+}
+
+/// Runs every job on its own std thread. `DepTree::run_with` keeps at most
+/// `concurrency` jobs in flight and joins each thread once its job is done,
+/// so no more than that many threads exist at once, but every unit still
+/// pays for a thread spawn. `DepTree::run` reuses a fixed set of threads
+/// instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadExecutor;
+
+impl Executor for ThreadExecutor {
+  type Handle = JoinHandle<()>;
+
+  fn spawn<F: FnOnce() + Send + 'static>(&self, job: F) -> Self::Handle {
+    thread::spawn(job)
+  }
+
+  fn join(&self, handle: Self::Handle) {
+    let _ = handle.join();
+  }
+}
+
+impl DepTree {
+  /// Calls `f` for every unit on up to `concurrency` threads, starting a unit
+  /// only after all its dependencies succeeded. A panic in `f` is propagated
+  /// to the caller.
+  pub fn run<E, F>(&self, concurrency: usize, f: F) -> RunReport<E>
+  where
+    E: Send,
+    F: Fn(DepId) -> Result<(), E> + Sync,
+  {
+    assert!(concurrency > 0, "runner needs at least one thread");
+    let (job_tx, job_rx) = mpsc::channel::<DepId>();
+    let (result_tx, result_rx) = mpsc::channel();
+    let job_rx = Mutex::new(job_rx);
+    thread::scope(|scope| {
+      for _ in 0..concurrency {
+        let result_tx = result_tx.clone();
+        let (job_rx, f) = (&job_rx, &f);
+        scope.spawn(move || loop {
+          let job = job_rx.lock().unwrap().recv();
+          let Ok(unit) = job else {
+            break;
+          };
+          let result = panic::catch_unwind(AssertUnwindSafe(|| f(unit)));
+          if result_tx.send((unit, result)).is_err() {
+            break;
+          }
+        });
+      }
+      drop(result_tx);
+      let report = drive(
+        self.scheduler(),
+        concurrency,
+        &result_rx,
+        |unit| job_tx.send(unit).unwrap(),
+        |_| {},
+      );
+      drop(job_tx);
+      report
+    })
+  }
+
+  /// Like `run`, but submits each unit as a job to `executor`.
+  pub fn run_with<X, E, F>(&self, executor: &X, concurrency: usize, f: F) -> RunReport<E>
+  where
+    X: Executor,
+    E: Send + 'static,
+    F: Fn(DepId) -> Result<(), E> + Send + Sync + 'static,
+  {
+    assert!(concurrency > 0, "runner needs at least one job in flight");
+    let f = Arc::new(f);
+    let (result_tx, result_rx) = mpsc::channel();
+    let handles = RefCell::new(BTreeMap::new());
+    drive(
+      self.scheduler(),
+      concurrency,
+      &result_rx,
+      |unit| {
+        let (f, result_tx) = (f.clone(), result_tx.clone());
+        let handle = executor.spawn(move || {
+          let result = panic::catch_unwind(AssertUnwindSafe(|| f(unit)));
+          let _ = result_tx.send((unit, result));
+        });
+        handles.borrow_mut().insert(unit, handle);
+      },
+      |unit| {
+        if let Some(handle) = handles.borrow_mut().remove(&unit) {
+          executor.join(handle);
+        }
+      },
+    )
+  }
+}
+
+/// Feeds ready units to `submit`, keeping at most `concurrency` in flight,
+/// until no unit can make progress. `finished` hears about every unit whose
+/// result came back.
+fn drive<E>(
+  mut scheduler: Scheduler,
+  concurrency: usize,
+  results: &mpsc::Receiver<JobResult<E>>,
+  mut submit: impl FnMut(DepId),
+  mut finished: impl FnMut(DepId),
+) -> RunReport<E> {
+  let mut failed = Vec::new();
+  let mut in_flight = 0;
+  loop {
+    while in_flight < concurrency {
+      let Some(unit) = scheduler.next_ready() else {
+        break;
+      };
+      submit(unit);
+      in_flight += 1;
+    }
+    if in_flight == 0 {
+      break;
+    }
+    let (unit, result) = results.recv().unwrap();
+    in_flight -= 1;
+    finished(unit);
+    match result.unwrap_or_else(|payload| panic::resume_unwind(payload)) {
+      Ok(()) => {
+        scheduler.mark_done(unit);
+      }
+      Err(error) => {
+        scheduler.mark_failed(unit);
+        failed.push((unit, error));
+      }
+    }
+  }
+  RunReport {
+    completed: scheduler.done().clone(),
+    failed,
+    skipped: scheduler.skipped().clone(),
+  }
+}
@@ -0,0 +1,468 @@
+use std::{
+  cmp::Reverse,
+  collections::{BTreeMap, BTreeSet},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{DepId, DepMap, DepTree};
+
+type ReadyKey = (Reverse<i64>, Reverse<usize>, usize, DepId);
+
+/// Shareable flag that stops a `Scheduler` from handing out more units. It can
+/// be triggered from another thread, e.g. a ctrl-C handler.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::SeqCst);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::SeqCst)
+  }
+}
+
+/// Where a scheduling session stood when it was cancelled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cancellation {
+  pub completed: BTreeSet<DepId>,
+  pub running: BTreeSet<DepId>,
+  pub not_started: BTreeSet<DepId>,
+}
+
+/// Hands out units whose dependencies are all done. Among ready units the
+/// highest priority goes first, then (with `prefer_critical_path`) the one
+/// heading the longest chain of dependents, then the one that became ready
+/// first. Units whose declared resources are exhausted are skipped until a
+/// running unit holding them is marked done.
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+  dependents: DepMap,
+  pending: BTreeMap<DepId, usize>,
+  priorities: BTreeMap<DepId, i64>,
+  chains: Option<BTreeMap<DepId, usize>>,
+  capacities: BTreeMap<String, usize>,
+  in_use: BTreeMap<String, usize>,
+  resources: BTreeMap<DepId, BTreeMap<String, usize>>,
+  ready: BTreeSet<ReadyKey>,
+  running: BTreeSet<DepId>,
+  done: BTreeSet<DepId>,
+  failed: BTreeSet<DepId>,
+  skipped: BTreeSet<DepId>,
+  deferred: BTreeMap<DepId, Instant>,
+  attempts: BTreeMap<DepId, usize>,
+  cancel: CancelToken,
+  seq: usize,
+}
+
+/// Single-threaded stepping through the tree in dependency order, for
+/// consumers that don't need the rest of the `Scheduler` API.
+#[derive(Clone, Debug)]
+pub struct ReadyIter {
+  scheduler: Scheduler,
+}
+
+impl ReadyIter {
+  pub fn next_ready(&mut self) -> Option<DepId> {
+    self.scheduler.next_ready()
+  }
+
+  /// Marks a unit handed out by `next_ready` as done and returns the units it
+  /// unlocked.
+  pub fn complete(&mut self, id: DepId) -> Vec<DepId> {
+    self.scheduler.mark_done(id)
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.scheduler.is_finished()
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SchedulerError {
+  #[error("unit `{unit:?}` asks for {amount} of `{tag}`, more than its capacity of {capacity}")]
+  OverCapacity { unit: DepId, tag: String, amount: usize, capacity: usize },
+}
+
+pub type SchedulerResult<T> = Result<T, SchedulerError>;
+
+fn check_capacity(unit: DepId, tag: &str, amount: usize, capacity: usize) -> SchedulerResult<()> {
+  if amount > capacity {
+    return Err(SchedulerError::OverCapacity { unit, tag: tag.to_owned(), amount, capacity });
+  }
+  Ok(())
+}
+
+impl DepTree {
+  pub fn scheduler(&self) -> Scheduler {
+    Scheduler::new(self)
+  }
+
+  pub fn ready_iter(&self) -> ReadyIter {
+    ReadyIter {
+      scheduler: self.scheduler(),
+    }
+  }
+}
+
+impl Scheduler {
+  pub fn new(tree: &DepTree) -> Self {
+    let dependents = tree.reverse_map();
+    let pending = dependents
+      .keys()
+      .map(|id| (*id, tree.inner.get(id).map_or(0, |deps| deps.len())))
+      .collect();
+    let mut scheduler = Self {
+      dependents,
+      pending,
+      priorities: BTreeMap::new(),
+      chains: None,
+      capacities: BTreeMap::new(),
+      in_use: BTreeMap::new(),
+      resources: BTreeMap::new(),
+      ready: BTreeSet::new(),
+      running: BTreeSet::new(),
+      done: BTreeSet::new(),
+      failed: BTreeSet::new(),
+      skipped: BTreeSet::new(),
+      deferred: BTreeMap::new(),
+      attempts: BTreeMap::new(),
+      cancel: CancelToken::new(),
+      seq: 0,
+    };
+    let initial = scheduler
+      .pending
+      .iter()
+      .filter(|(_, &pending)| pending == 0)
+      .map(|(&id, _)| id)
+      .collect::<Vec<_>>();
+    for id in initial {
+      scheduler.push_ready(id);
+    }
+    scheduler
+  }
+
+  pub fn with_priority(mut self, id: DepId, priority: i64) -> Self {
+    self.priorities.insert(id, priority);
+    self.refresh();
+    self
+  }
+
+  /// Breaks priority ties by the number of units in the longest chain of
+  /// transitive dependents waiting on each unit.
+  pub fn prefer_critical_path(mut self) -> Self {
+    let mut chains = BTreeMap::new();
+    for &id in self.dependents.keys() {
+      self.chain_length(id, &mut chains);
+    }
+    self.chains = Some(chains);
+    self.refresh();
+    self
+  }
+
+  pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+    self.cancel = token;
+    self
+  }
+
+  pub fn cancel_token(&self) -> CancelToken {
+    self.cancel.clone()
+  }
+
+  /// Stops handing out units. Running units can still be marked done or
+  /// failed; `cancellation` reports the final state once they are.
+  pub fn cancel(&mut self) -> Cancellation {
+    self.cancel.cancel();
+    self.cancellation()
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancel.is_cancelled()
+  }
+
+  pub fn cancellation(&self) -> Cancellation {
+    Cancellation {
+      completed: self.done.clone(),
+      running: self.running.clone(),
+      not_started: self
+        .pending
+        .keys()
+        .filter(|id| {
+          !self.done.contains(id) && !self.running.contains(id) && !self.failed.contains(id)
+        })
+        .copied()
+        .collect(),
+    }
+  }
+
+  /// Caps how many units of `tag` running units may hold at once. Tags without
+  /// a capacity are unconstrained. Fails if a unit already asks for more of
+  /// `tag` than `capacity`.
+  pub fn with_capacity(mut self, tag: impl Into<String>, capacity: usize) -> SchedulerResult<Self> {
+    let tag = tag.into();
+    for (&id, resources) in self.resources.iter() {
+      if let Some(&amount) = resources.get(&tag) {
+        check_capacity(id, &tag, amount, capacity)?;
+      }
+    }
+    self.capacities.insert(tag, capacity);
+    Ok(self)
+  }
+
+  /// Declares the resources `id` holds while running, e.g. `[("gpu", 1)]`,
+  /// adding up repeated tags. Fails if `id` asks for more of a tag than its
+  /// capacity, since it could never be handed out and its dependents would
+  /// wait forever.
+  pub fn with_resources<T: Into<String>>(
+    mut self,
+    id: DepId,
+    resources: impl IntoIterator<Item = (T, usize)>,
+  ) -> SchedulerResult<Self> {
+    let mut totals = BTreeMap::<String, usize>::new();
+    for (tag, amount) in resources {
+      let total = totals.entry(tag.into()).or_default();
+      *total = total.saturating_add(amount);
+    }
+    for (tag, &amount) in &totals {
+      if let Some(&capacity) = self.capacities.get(tag) {
+        check_capacity(id, tag, amount, capacity)?;
+      }
+    }
+    self.resources.insert(id, totals);
+    Ok(self)
+  }
+
+  /// Ready units in the order `next_ready` would hand them out.
+  pub fn ready(&self) -> Vec<DepId> {
+    if self.is_cancelled() {
+      return Vec::new();
+    }
+    self.ready.iter().map(|key| key.3).collect()
+  }
+
+  /// Takes the next ready unit whose resources are available and marks it as
+  /// running.
+  pub fn next_ready(&mut self) -> Option<DepId> {
+    if self.is_cancelled() {
+      return None;
+    }
+    self.promote_deferred();
+    let key = *self.ready.iter().find(|key| self.fits(key.3))?;
+    self.ready.remove(&key);
+    let id = key.3;
+    for (tag, amount) in self.resources.get(&id).into_iter().flatten() {
+      *self.in_use.entry(tag.clone()).or_insert(0) += amount;
+    }
+    self.running.insert(id);
+    Some(id)
+  }
+
+  /// Marks a running unit as done and returns the units it made ready.
+  pub fn mark_done(&mut self, id: DepId) -> Vec<DepId> {
+    if !self.running.remove(&id) {
+      return Vec::new();
+    }
+    self.release(id);
+    self.done.insert(id);
+    let mut unlocked = Vec::new();
+    for dependent in self.dependents[&id].clone() {
+      let pending = self.pending.get_mut(&dependent).unwrap();
+      *pending -= 1;
+      if *pending == 0 && !self.skipped.contains(&dependent) {
+        self.push_ready(dependent);
+        unlocked.push(dependent);
+      }
+    }
+    unlocked
+  }
+
+  /// Marks a running unit as failed and skips every unit that transitively
+  /// depends on it. Returns the newly skipped units; unrelated branches keep
+  /// running.
+  pub fn mark_failed(&mut self, id: DepId) -> Vec<DepId> {
+    if !self.running.remove(&id) {
+      return Vec::new();
+    }
+    self.release(id);
+    self.failed.insert(id);
+    let mut skipped = Vec::new();
+    let mut stack = vec![id];
+    while let Some(unit) = stack.pop() {
+      for &dependent in &self.dependents[&unit] {
+        if self.skipped.insert(dependent) {
+          skipped.push(dependent);
+          stack.push(dependent);
+        }
+      }
+    }
+    skipped.sort();
+    skipped
+  }
+
+  /// Puts a running unit back into the ready set once `delay_hint` has passed,
+  /// without unblocking its dependents.
+  pub fn mark_retry(&mut self, id: DepId, delay_hint: Duration) {
+    if !self.running.remove(&id) {
+      return;
+    }
+    self.release(id);
+    *self.attempts.entry(id).or_insert(0) += 1;
+    self.deferred.insert(id, Instant::now() + delay_hint);
+  }
+
+  /// How many times `id` was sent back with `mark_retry`.
+  pub fn retries(&self, id: DepId) -> usize {
+    self.attempts.get(&id).copied().unwrap_or(0)
+  }
+
+  /// When the earliest deferred unit becomes ready again, for drivers that
+  /// want to sleep instead of polling `next_ready`.
+  pub fn next_retry_at(&self) -> Option<Instant> {
+    self.deferred.values().min().copied()
+  }
+
+  pub fn running(&self) -> &BTreeSet<DepId> {
+    &self.running
+  }
+
+  pub fn done(&self) -> &BTreeSet<DepId> {
+    &self.done
+  }
+
+  pub fn failed(&self) -> &BTreeSet<DepId> {
+    &self.failed
+  }
+
+  pub fn skipped(&self) -> &BTreeSet<DepId> {
+    &self.skipped
+  }
+
+  pub fn is_finished(&self) -> bool {
+    let idle = self.ready.is_empty() && self.deferred.is_empty();
+    (idle || self.is_cancelled()) && self.running.is_empty()
+  }
+
+  fn promote_deferred(&mut self) {
+    let now = Instant::now();
+    let mut due = Vec::new();
+    self.deferred.retain(|&id, &mut at| {
+      if at <= now {
+        due.push(id);
+      }
+      at > now
+    });
+    for id in due {
+      self.push_ready(id);
+    }
+  }
+
+  fn fits(&self, id: DepId) -> bool {
+    self.resources.get(&id).into_iter().flatten().all(|(tag, amount)| {
+      self.capacities.get(tag).is_none_or(|&capacity| {
+        self.in_use.get(tag).copied().unwrap_or(0) + amount <= capacity
+      })
+    })
+  }
+
+  fn release(&mut self, id: DepId) {
+    for (tag, amount) in self.resources.get(&id).into_iter().flatten() {
+      if let Some(in_use) = self.in_use.get_mut(tag) {
+        *in_use -= amount;
+      }
+    }
+  }
+
+  fn push_ready(&mut self, id: DepId) {
+    self.ready.insert(self.key(self.seq, id));
+    self.seq += 1;
+  }
+
+  fn key(&self, seq: usize, id: DepId) -> ReadyKey {
+    let priority = self.priorities.get(&id).copied().unwrap_or(0);
+    let chain = self.chains.as_ref().map_or(0, |chains| chains[&id]);
+    (Reverse(priority), Reverse(chain), seq, id)
+  }
+
+  /// Recomputes the ready keys after a priority change, keeping the order in
+  /// which units became ready.
+  fn refresh(&mut self) {
+    for (.., seq, id) in std::mem::take(&mut self.ready) {
+      self.ready.insert(self.key(seq, id));
+    }
+  }
+
+  fn chain_length(&self, id: DepId, chains: &mut BTreeMap<DepId, usize>) -> usize {
+    if let Some(&length) = chains.get(&id) {
+      return length;
+    }
+    let length = 1 + self.dependents[&id]
+      .iter()
+      .map(|&dependent| self.chain_length(dependent, chains))
+      .max()
+      .unwrap_or(0);
+    chains.insert(id, length);
+    length
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::DepTreeBuilder;
+
+  /// Four independent units.
+  fn scheduler() -> Scheduler {
+    let mut builder = DepTreeBuilder::new();
+    for id in 1..=4 {
+      builder.with_dep((id, 0), Vec::new());
+    }
+    builder.build().unwrap().scheduler()
+  }
+
+  #[test]
+  fn resources_limit_what_runs_at_once() {
+    let mut scheduler = scheduler()
+      .with_capacity("gpu", 2)
+      .and_then(|scheduler| scheduler.with_resources((1, 0), [("gpu", 1)]))
+      .and_then(|scheduler| scheduler.with_resources((2, 0), [("gpu", 2), ("disk", 9)]))
+      .and_then(|scheduler| scheduler.with_resources((3, 0), [("gpu", 1)]))
+      .unwrap();
+    assert_eq!(scheduler.next_ready(), Some((1, 0)));
+    assert_eq!(scheduler.next_ready(), Some((3, 0)));
+    assert_eq!(scheduler.next_ready(), Some((4, 0)));
+    assert_eq!(scheduler.next_ready(), None);
+    scheduler.mark_done((1, 0));
+    assert_eq!(scheduler.next_ready(), None);
+    scheduler.mark_failed((3, 0));
+    assert_eq!(scheduler.next_ready(), Some((2, 0)));
+    scheduler.mark_retry((2, 0), Duration::ZERO);
+    assert_eq!(scheduler.next_ready(), Some((2, 0)));
+    scheduler.mark_done((2, 0));
+    scheduler.mark_done((4, 0));
+    assert!(scheduler.is_finished());
+    assert_eq!(scheduler.in_use.get("gpu"), Some(&0));
+  }
+
+  #[test]
+  fn repeated_tags_add_up() {
+    let scheduler = scheduler().with_resources((1, 0), [("gpu", 1), ("gpu", 1)]).unwrap();
+    assert_eq!(scheduler.resources[&(1, 0)], BTreeMap::from([("gpu".to_owned(), 2)]));
+    let error = SchedulerError::OverCapacity { unit: (1, 0), tag: "gpu".to_owned(), amount: 2, capacity: 1 };
+    assert_eq!(scheduler.clone().with_capacity("gpu", 1).unwrap_err(), error);
+    let capped = scheduler.with_capacity("cpu", 1).unwrap();
+    let error = capped.with_resources((2, 0), [("cpu", 1), ("cpu", usize::MAX)]).unwrap_err();
+    let message = format!("unit `(2, 0)` asks for {} of `cpu`, more than its capacity of 1", usize::MAX);
+    assert_eq!(error.to_string(), message);
+  }
+}
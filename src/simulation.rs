@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{DepId, DepTree, Scheduler};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledUnit {
+  pub unit: DepId,
+  pub worker: usize,
+  pub start: f64,
+  pub end: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simulation {
+  pub makespan: f64,
+  /// Busy time of each worker divided by the makespan.
+  pub utilization: Vec<f64>,
+  /// Units in the order they were started.
+  pub schedule: Vec<ScheduledUnit>,
+}
+
+impl DepTree {
+  /// Simulates running every unit on `n_workers` workers. Units without an
+  /// entry in `durations` take no time.
+  pub fn simulate(&self, durations: &BTreeMap<DepId, f64>, n_workers: usize) -> Simulation {
+    self.scheduler().simulate(durations, n_workers)
+  }
+}
+
+impl Scheduler {
+  /// Like `DepTree::simulate`, but honours this scheduler's priorities and
+  /// resource limits.
+  pub fn simulate(mut self, durations: &BTreeMap<DepId, f64>, n_workers: usize) -> Simulation {
+    assert!(n_workers > 0, "simulation needs at least one worker");
+    let mut free = (0..n_workers).collect::<BTreeSet<_>>();
+    let mut busy = vec![0.0; n_workers];
+    let mut running: Vec<ScheduledUnit> = Vec::new();
+    let mut schedule = Vec::new();
+    let mut now = 0.0;
+    loop {
+      while let Some(&worker) = free.first() {
+        let Some(unit) = self.next_ready() else {
+          break;
+        };
+        free.remove(&worker);
+        let end = now + durations.get(&unit).copied().unwrap_or(0.0);
+        let scheduled = ScheduledUnit { unit, worker, start: now, end };
+        schedule.push(scheduled);
+        running.push(scheduled);
+      }
+      let Some(next) = running.iter().map(|scheduled| scheduled.end).reduce(f64::min) else {
+        break;
+      };
+      now = next;
+      running.retain(|scheduled| {
+        if scheduled.end > now {
+          return true;
+        }
+        busy[scheduled.worker] += scheduled.end - scheduled.start;
+        free.insert(scheduled.worker);
+        self.mark_done(scheduled.unit);
+        false
+      });
+    }
+    let utilization = busy
+      .into_iter()
+      .map(|busy| if now > 0.0 { busy / now } else { 0.0 })
+      .collect();
+    Simulation { makespan: now, utilization, schedule }
+  }
+}
@@ -0,0 +1,623 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt,
+  ops::{Bound, RangeBounds, RangeInclusive},
+};
+
+use thiserror::Error;
+
+use crate::{label, DepId, DepTree, DepTreeBuilder, DepTreeBuilderError};
+
+/// Versions of package `id` a dependency accepts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Requirement {
+  pub id: u64,
+  pub versions: RangeInclusive<usize>,
+}
+
+impl Requirement {
+  pub fn new(id: u64, versions: impl RangeBounds<usize>) -> Self {
+    let start = match versions.start_bound() {
+      Bound::Included(&start) => start,
+      Bound::Excluded(&start) => start.saturating_add(1),
+      Bound::Unbounded => 0,
+    };
+    let end = match versions.end_bound() {
+      Bound::Included(&end) => end,
+      Bound::Excluded(&end) => end.saturating_sub(1),
+      Bound::Unbounded => usize::MAX,
+    };
+    Self { id, versions: start..=end }
+  }
+
+  pub fn exact(id: u64, version: usize) -> Self {
+    Self::new(id, version..=version)
+  }
+
+  pub fn at_least(id: u64, version: usize) -> Self {
+    Self::new(id, version..)
+  }
+
+  pub fn matches(&self, version: usize) -> bool {
+    self.versions.contains(&version)
+  }
+}
+
+impl fmt::Display for Requirement {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (&start, &end) = (self.versions.start(), self.versions.end());
+    if start == end {
+      write!(f, "{}", label((self.id, start)))
+    } else if end == usize::MAX {
+      write!(f, "{} at version {start} or later", self.id)
+    } else {
+      write!(f, "{} at versions {start} to {end}", self.id)
+    }
+  }
+}
+
+#[derive(Clone, Debug, Error)]
+pub enum SolveError {
+  #[error("unit `{0:?}` is not in the registry")]
+  UnknownRoot(DepId),
+  #[error("no consistent set of versions exists:\n{0}")]
+  Conflict(String),
+  #[error(transparent)]
+  Invalid(#[from] DepTreeBuilderError),
+}
+
+/// Every known version of every package with what it requires, the input to
+/// version solving. A unit's version is the version of its package.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+  packages: BTreeMap<u64, BTreeMap<usize, Vec<Requirement>>>,
+}
+
+impl Registry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Registers `unit` as an available version of its package, replacing any
+  /// earlier requirements of that version.
+  pub fn with_version(mut self, unit: DepId, requirements: impl IntoIterator<Item = Requirement>) -> Self {
+    let (id, version) = unit;
+    self.packages.entry(id).or_default().insert(version, requirements.into_iter().collect());
+    self
+  }
+
+  /// Requirements of `unit`, `None` if it isn't registered.
+  pub fn requirements(&self, (id, version): DepId) -> Option<&[Requirement]> {
+    self.packages.get(&id)?.get(&version).map(Vec::as_slice)
+  }
+
+  /// Picks one version of every package `root` needs, preferring newer
+  /// versions, and returns the tree of the selected units. Solving follows
+  /// PubGrub: conflicts are traced back to their root cause and learned, and
+  /// when no assignment exists the error walks through the derivation of why.
+  pub fn solve(&self, root: DepId) -> Result<DepTree, SolveError> {
+    if self.requirements(root).is_none() {
+      return Err(SolveError::UnknownRoot(root));
+    }
+    let mut solver = Solver { registry: self, root, incompatibilities: Vec::new(), assignments: Vec::new() };
+    let selected = solver.run()?;
+    self.tree(&selected)
+  }
+
+  /// Minimal Version Selection as in Go modules: every requirement points at
+  /// the oldest registered version it accepts, and each package gets the
+  /// newest of the versions pointed at from anything reachable, superseded
+  /// versions included. `root`'s own package stays at `root`. The result only
+  /// changes when requirements do, not when new versions are published.
+  /// Selected versions past a requirement's upper bound are reported as a
+  /// conflict, and the tree holds what the root reaches through the selection.
+  pub fn solve_minimal(&self, root: DepId) -> Result<DepTree, SolveError> {
+    if self.requirements(root).is_none() {
+      return Err(SolveError::UnknownRoot(root));
+    }
+    // Newest version pointed at per package, with the unit pointing at it.
+    let mut selected = BTreeMap::<u64, (usize, Option<DepId>)>::from([(root.0, (root.1, None))]);
+    let mut visited = BTreeSet::from([root]);
+    let mut stack = vec![root];
+    while let Some(unit) = stack.pop() {
+      for requirement in &self.packages[&unit.0][&unit.1] {
+        let oldest = self.packages.get(&requirement.id).and_then(|versions| versions.range(requirement.versions.clone()).next());
+        let Some((&version, _)) = oldest else {
+          return Err(SolveError::Conflict(format!(
+            "{} depends on {requirement}, which matches no registered version.",
+            label(unit)
+          )));
+        };
+        let newest = selected.entry(requirement.id).or_insert((version, Some(unit)));
+        if requirement.id != root.0 && version > newest.0 {
+          *newest = (version, Some(unit));
+        }
+        if visited.insert((requirement.id, version)) {
+          stack.push((requirement.id, version));
+        }
+      }
+    }
+
+    let mut reached = BTreeMap::from([(root.0, root.1)]);
+    let mut stack = vec![root];
+    while let Some(unit) = stack.pop() {
+      for requirement in &self.packages[&unit.0][&unit.1] {
+        let (version, raised_by) = selected[&requirement.id];
+        if !requirement.matches(version) {
+          let reason = match raised_by {
+            Some(by) => format!("{} needs at least {}", label(by), label((requirement.id, version))),
+            None => format!("{} is the root", label(root)),
+          };
+          return Err(SolveError::Conflict(format!("{} depends on {requirement}, but {reason}.", label(unit))));
+        }
+        if reached.insert(requirement.id, version).is_none() {
+          stack.push((requirement.id, version));
+        }
+      }
+    }
+    self.tree(&reached)
+  }
+
+  /// Tree of `selected` package versions with an edge per requirement.
+  fn tree(&self, selected: &BTreeMap<u64, usize>) -> Result<DepTree, SolveError> {
+    let mut builder = DepTreeBuilder::new();
+    for (&id, &version) in selected {
+      let deps = self.packages[&id][&version]
+        .iter()
+        .filter(|requirement| requirement.id != id)
+        .map(|requirement| (requirement.id, selected[&requirement.id]));
+      builder.with_dep((id, version), deps.collect());
+    }
+    Ok(*builder.build()?)
+  }
+
+  fn universe(&self, id: u64) -> BTreeSet<usize> {
+    self.packages.get(&id).map_or_else(BTreeSet::new, |versions| versions.keys().copied().collect())
+  }
+}
+
+/// Known versions a package may be selected at, and whether it may be left
+/// out altogether. PubGrub's positive terms don't allow leaving it out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Term {
+  versions: BTreeSet<usize>,
+  absent: bool,
+}
+
+impl Term {
+  fn any(universe: BTreeSet<usize>) -> Self {
+    Self { versions: universe, absent: true }
+  }
+
+  fn positive(versions: BTreeSet<usize>) -> Self {
+    Self { versions, absent: false }
+  }
+
+  fn negate(&self, universe: &BTreeSet<usize>) -> Self {
+    Self { versions: universe - &self.versions, absent: !self.absent }
+  }
+
+  fn intersect(&self, other: &Self) -> Self {
+    Self { versions: &self.versions & &other.versions, absent: self.absent && other.absent }
+  }
+
+  fn union(&self, other: &Self) -> Self {
+    Self { versions: &self.versions | &other.versions, absent: self.absent || other.absent }
+  }
+
+  fn subset_of(&self, other: &Self) -> bool {
+    self.versions.is_subset(&other.versions) && (!self.absent || other.absent)
+  }
+
+  fn disjoint(&self, other: &Self) -> bool {
+    self.versions.is_disjoint(&other.versions) && !(self.absent && other.absent)
+  }
+}
+
+#[derive(Clone, Debug)]
+enum Cause {
+  Root,
+  Dependency(DepId, Requirement),
+  Derived(usize, usize),
+}
+
+/// Terms that can't all hold at once.
+#[derive(Clone, Debug)]
+struct Incompatibility {
+  terms: BTreeMap<u64, Term>,
+  cause: Cause,
+}
+
+struct Assignment {
+  id: u64,
+  term: Term,
+  level: usize,
+  /// Incompatibility it was derived from, `None` for decisions.
+  cause: Option<usize>,
+}
+
+enum Relation {
+  Satisfied,
+  Contradicted,
+  AlmostSatisfied(u64),
+  Inconclusive,
+}
+
+struct Solver<'a> {
+  registry: &'a Registry,
+  root: DepId,
+  incompatibilities: Vec<Incompatibility>,
+  assignments: Vec<Assignment>,
+}
+
+impl Solver<'_> {
+  fn run(&mut self) -> Result<BTreeMap<u64, usize>, SolveError> {
+    let (root_id, root_version) = self.root;
+    let universe = self.registry.universe(root_id);
+    let root_term = Term::positive(BTreeSet::from([root_version])).negate(&universe);
+    self.add(BTreeMap::from([(root_id, root_term)]), Cause::Root);
+    let mut expanded = BTreeSet::new();
+    let mut next = root_id;
+    loop {
+      self.propagate(next)?;
+      let decided = self.assignments.iter().filter(|a| a.cause.is_none()).map(|a| a.id).collect::<BTreeSet<_>>();
+      let candidates = self.assignments.iter().map(|a| a.id).filter(|id| !decided.contains(id)).collect::<BTreeSet<_>>();
+      let choice = candidates
+        .into_iter()
+        .map(|id| (id, self.current(id, self.assignments.len())))
+        .filter(|(_, term)| !term.absent)
+        .min_by_key(|(id, term)| (term.versions.len(), *id));
+      let Some((id, term)) = choice else {
+        break;
+      };
+      // Positive terms always keep a known version, see `derive`.
+      let version = *term.versions.last().unwrap();
+      if expanded.insert((id, version)) {
+        for requirement in &self.registry.packages[&id][&version] {
+          if requirement.id == id && requirement.matches(version) {
+            continue;
+          }
+          let universe = self.registry.universe(requirement.id);
+          let accepted = universe.iter().copied().filter(|&v| requirement.matches(v)).collect();
+          let mut terms = BTreeMap::from([(requirement.id, Term::positive(accepted).negate(&universe))]);
+          // A version requiring another version of its own package rules
+          // itself out.
+          terms.insert(id, Term::positive(BTreeSet::from([version])));
+          self.add(terms, Cause::Dependency((id, version), requirement.clone()));
+        }
+      }
+      let level = decided.len() + 1;
+      self.assignments.push(Assignment { id, term: Term::positive(BTreeSet::from([version])), level, cause: None });
+      next = id;
+    }
+    Ok(self.assignments.iter().filter(|a| a.cause.is_none()).map(|a| (a.id, *a.term.versions.first().unwrap())).collect())
+  }
+
+  /// Adds an incompatibility, dropping terms that any assignment satisfies.
+  fn add(&mut self, mut terms: BTreeMap<u64, Term>, cause: Cause) -> usize {
+    terms.retain(|&id, term| *term != Term::any(self.registry.universe(id)));
+    self.incompatibilities.push(Incompatibility { terms, cause });
+    self.incompatibilities.len() - 1
+  }
+
+  /// Intersection of the first `len` assignments to `id`.
+  fn current(&self, id: u64, len: usize) -> Term {
+    self.assignments[..len]
+      .iter()
+      .filter(|a| a.id == id)
+      .fold(Term::any(self.registry.universe(id)), |term, a| term.intersect(&a.term))
+  }
+
+  fn level(&self) -> usize {
+    self.assignments.iter().filter(|a| a.cause.is_none()).count()
+  }
+
+  fn relation(&self, index: usize) -> Relation {
+    let mut unsatisfied = None;
+    let mut inconclusive = false;
+    for (&id, term) in &self.incompatibilities[index].terms {
+      let current = self.current(id, self.assignments.len());
+      if current.subset_of(term) {
+        continue;
+      }
+      if current.disjoint(term) {
+        return Relation::Contradicted;
+      }
+      inconclusive |= unsatisfied.replace(id).is_some();
+    }
+    match unsatisfied {
+      None => Relation::Satisfied,
+      Some(_) if inconclusive => Relation::Inconclusive,
+      Some(id) => Relation::AlmostSatisfied(id),
+    }
+  }
+
+  /// Assigns the negation of `index`'s term for `id`. That leaves a positive
+  /// assignment with some version: had it none, the term would already have
+  /// been satisfied rather than almost.
+  fn derive(&mut self, id: u64, index: usize) {
+    let term = self.incompatibilities[index].terms[&id].negate(&self.registry.universe(id));
+    let level = self.level();
+    self.assignments.push(Assignment { id, term, level, cause: Some(index) });
+  }
+
+  fn propagate(&mut self, start: u64) -> Result<(), SolveError> {
+    let mut changed = vec![start];
+    while let Some(id) = changed.pop() {
+      for index in (0..self.incompatibilities.len()).rev() {
+        if !self.incompatibilities[index].terms.contains_key(&id) {
+          continue;
+        }
+        match self.relation(index) {
+          Relation::Satisfied => {
+            let cause = self.resolve_conflict(index)?;
+            let Relation::AlmostSatisfied(id) = self.relation(cause) else {
+              unreachable!("a learned incompatibility is almost satisfied after backjumping");
+            };
+            self.derive(id, cause);
+            changed.clear();
+            changed.push(id);
+            break;
+          }
+          Relation::AlmostSatisfied(id) => {
+            self.derive(id, index);
+            changed.push(id);
+          }
+          Relation::Contradicted | Relation::Inconclusive => {}
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Index of the assignment that made `terms` satisfied, with the highest
+  /// decision level among the assignments needed besides it.
+  fn satisfier(&self, terms: &BTreeMap<u64, Term>) -> (usize, usize) {
+    let mut satisfied_at = BTreeMap::new();
+    for &id in terms.keys() {
+      let at = (1..=self.assignments.len())
+        .find(|&len| self.assignments[len - 1].id == id && self.current(id, len).subset_of(&terms[&id]))
+        .unwrap();
+      satisfied_at.insert(id, at - 1);
+    }
+    let (&id, &satisfier) = satisfied_at.iter().max_by_key(|(_, &at)| at).unwrap();
+    let mut previous = satisfied_at
+      .iter()
+      .filter(|&(&other, _)| other != id)
+      .map(|(_, &at)| self.assignments[at].level)
+      .max()
+      .unwrap_or(0);
+    let term = &self.assignments[satisfier].term;
+    if !term.subset_of(&terms[&id]) {
+      let before = (1..=satisfier)
+        .find(|&len| self.assignments[len - 1].id == id && self.current(id, len).intersect(term).subset_of(&terms[&id]))
+        .unwrap();
+      previous = previous.max(self.assignments[before - 1].level);
+    }
+    (satisfier, previous)
+  }
+
+  fn is_terminal(&self, terms: &BTreeMap<u64, Term>) -> bool {
+    let (root_id, root_version) = self.root;
+    match terms.iter().next() {
+      None => true,
+      Some((&id, term)) => terms.len() == 1 && id == root_id && !term.absent && term.versions.contains(&root_version),
+    }
+  }
+
+  /// Learns the root cause of the conflict at `index` and backjumps to where
+  /// it becomes almost satisfied, returning its index.
+  fn resolve_conflict(&mut self, mut index: usize) -> Result<usize, SolveError> {
+    loop {
+      let terms = self.incompatibilities[index].terms.clone();
+      if self.is_terminal(&terms) {
+        return Err(SolveError::Conflict(self.explain(index)));
+      }
+      let (satisfier, previous) = self.satisfier(&terms);
+      let assignment = &self.assignments[satisfier];
+      let Some(cause) = assignment.cause.filter(|_| previous == assignment.level) else {
+        self.assignments.retain(|a| a.level <= previous);
+        return Ok(index);
+      };
+
+      let id = assignment.id;
+      let universe = self.registry.universe(id);
+      let mut prior = BTreeMap::<u64, Term>::new();
+      for (&other, term) in terms.iter().chain(&self.incompatibilities[cause].terms) {
+        if other != id {
+          let merged = prior.get(&other).map_or_else(|| term.clone(), |existing| existing.union(term));
+          prior.insert(other, merged);
+        }
+      }
+      if !assignment.term.subset_of(&terms[&id]) {
+        let difference = assignment.term.intersect(&terms[&id].negate(&universe));
+        prior.insert(id, difference.negate(&universe));
+      }
+      index = self.add(prior, Cause::Derived(index, cause));
+    }
+  }
+
+  fn describe_term(&self, id: u64, term: &Term) -> String {
+    let universe = self.registry.universe(id);
+    let versions = if term.absent { &universe - &term.versions } else { term.versions.clone() };
+    let named = if versions.len() == 1 {
+      label((id, *versions.first().unwrap()))
+    } else if versions == universe {
+      format!("any version of {id}")
+    } else {
+      let list = versions.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+      format!("{id} at one of {list}")
+    };
+    if term.absent {
+      format!("not {named}")
+    } else {
+      named
+    }
+  }
+
+  fn describe(&self, index: usize) -> String {
+    let incompatibility = &self.incompatibilities[index];
+    match &incompatibility.cause {
+      Cause::Root => format!("{} is required", label(self.root)),
+      Cause::Dependency(unit, requirement) => {
+        let known = self.registry.universe(requirement.id);
+        if known.iter().any(|&version| requirement.matches(version)) {
+          format!("{} depends on {requirement}", label(*unit))
+        } else {
+          format!("{} depends on {requirement}, which matches no registered version", label(*unit))
+        }
+      }
+      Cause::Derived(..) => {
+        let (positive, negative): (Vec<_>, Vec<_>) = incompatibility.terms.iter().partition(|(_, term)| !term.absent);
+        let join = |terms: &[(&u64, &Term)], negate: bool, separator: &str| {
+          terms
+            .iter()
+            .map(|&(&id, term)| {
+              let term = if negate { term.negate(&self.registry.universe(id)) } else { term.clone() };
+              self.describe_term(id, &term)
+            })
+            .collect::<Vec<_>>()
+            .join(separator)
+        };
+        match (positive.len(), negative.len()) {
+          (0, 0) => "version solving failed".to_owned(),
+          (1, 0) => format!("{} is forbidden", join(&positive, false, "")),
+          (_, 0) => format!("{} are incompatible", join(&positive, false, " and ")),
+          (0, _) => format!("one of {} is required", join(&negative, true, " or ")),
+          _ => format!("{} requires {}", join(&positive, false, " and "), join(&negative, true, " or ")),
+        }
+      }
+    }
+  }
+
+  /// Numbered derivation of the incompatibility at `index`, each step citing
+  /// earlier steps by number.
+  fn explain(&self, index: usize) -> String {
+    let mut lines = Vec::new();
+    let mut numbers = BTreeMap::new();
+    self.explain_into(index, &mut lines, &mut numbers);
+    if lines.is_empty() {
+      lines.push(format!("{}.", self.describe(index)));
+    }
+    lines.into_iter().enumerate().map(|(i, line)| format!("{}. {line}", i + 1)).collect::<Vec<_>>().join("\n")
+  }
+
+  fn explain_into(&self, index: usize, lines: &mut Vec<String>, numbers: &mut BTreeMap<usize, usize>) {
+    let Cause::Derived(first, second) = self.incompatibilities[index].cause else {
+      return;
+    };
+    for cause in [first, second] {
+      if matches!(self.incompatibilities[cause].cause, Cause::Derived(..)) && !numbers.contains_key(&cause) {
+        self.explain_into(cause, lines, numbers);
+      }
+    }
+    let cite = |cause: usize| match numbers.get(&cause) {
+      Some(number) => format!("{} ({number})", self.describe(cause)),
+      None => self.describe(cause),
+    };
+    lines.push(format!("Because {} and {}, {}.", cite(first), cite(second), self.describe(index)));
+    numbers.insert(index, lines.len());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ROOT: DepId = (0, 1);
+
+  fn units(tree: &DepTree) -> Vec<DepId> {
+    tree.reverse_map().into_keys().collect()
+  }
+
+  fn conflict(result: Result<DepTree, SolveError>) -> String {
+    match result {
+      Err(SolveError::Conflict(message)) => message,
+      other => panic!("expected a conflict, got {other:?}"),
+    }
+  }
+
+  #[test]
+  fn requirements_normalize_ranges() {
+    assert_eq!(Requirement::new(1, 2..5).versions, 2..=4);
+    assert_eq!(Requirement::at_least(1, 3).versions, 3..=usize::MAX);
+    assert!(Requirement::exact(1, 3).matches(3) && !Requirement::exact(1, 3).matches(4));
+    assert_eq!(Requirement::new(1, ..=7).to_string(), "1 at versions 0 to 7");
+    assert_eq!(Requirement::at_least(1, 3).to_string(), "1 at version 3 or later");
+  }
+
+  #[test]
+  fn solve_prefers_newer_versions() {
+    let registry = Registry::new()
+      .with_version(ROOT, [Requirement::at_least(1, 1), Requirement::new(2, ..)])
+      .with_version((1, 1), [])
+      .with_version((1, 2), [Requirement::new(2, ..=1)])
+      .with_version((2, 1), [])
+      .with_version((2, 2), []);
+    let tree = registry.solve(ROOT).unwrap();
+    assert_eq!(units(&tree), [ROOT, (1, 2), (2, 1)]);
+    assert_eq!(*tree.inner[&ROOT], [(1, 2), (2, 1)]);
+    assert_eq!(*tree.inner[&(1, 2)], [(2, 1)]);
+  }
+
+  #[test]
+  fn solve_backtracks_out_of_conflicts() {
+    let registry = Registry::new()
+      .with_version(ROOT, [Requirement::new(1, ..), Requirement::new(2, ..)])
+      .with_version((1, 1), [Requirement::exact(3, 1)])
+      .with_version((1, 2), [Requirement::exact(3, 2)])
+      .with_version((2, 1), [Requirement::exact(3, 1)])
+      .with_version((3, 1), [])
+      .with_version((3, 2), []);
+    assert_eq!(units(&registry.solve(ROOT).unwrap()), [ROOT, (1, 1), (2, 1), (3, 1)]);
+  }
+
+  #[test]
+  fn solve_explains_failures() {
+    let registry = Registry::new()
+      .with_version(ROOT, [Requirement::exact(1, 1), Requirement::new(2, ..)])
+      .with_version((1, 1), [])
+      .with_version((1, 2), [])
+      .with_version((2, 1), [Requirement::exact(1, 2)]);
+    let message = conflict(registry.solve(ROOT));
+    assert_eq!(
+      message,
+      "1. Because (2, 1) depends on (1, 2) and (0, 1) depends on (1, 1), (0, 1) and (2, 1) are incompatible.\n\
+       2. Because (0, 1) depends on 2 at version 0 or later and (0, 1) and (2, 1) are incompatible (1), \
+       (0, 1) is forbidden."
+    );
+    assert!(matches!(registry.solve((9, 9)), Err(SolveError::UnknownRoot((9, 9)))));
+    let missing = Registry::new().with_version(ROOT, [Requirement::exact(4, 1)]);
+    conflict(missing.solve(ROOT));
+  }
+
+  #[test]
+  fn solve_minimal_picks_oldest_allowed() {
+    let registry = Registry::new()
+      .with_version(ROOT, [Requirement::at_least(1, 1), Requirement::at_least(2, 1)])
+      .with_version((1, 1), [Requirement::at_least(3, 2)])
+      .with_version((1, 2), [])
+      .with_version((1, 3), [])
+      .with_version((2, 1), [Requirement::at_least(1, 2)])
+      .with_version((2, 2), [])
+      .with_version((3, 1), [])
+      .with_version((3, 2), []);
+    let tree = registry.solve_minimal(ROOT).unwrap();
+    assert_eq!(units(&tree), [ROOT, (1, 2), (2, 1)]);
+    assert_eq!(*tree.inner[&(2, 1)], [(1, 2)]);
+  }
+
+  #[test]
+  fn solve_minimal_reports_upper_bounds() {
+    let registry = Registry::new()
+      .with_version(ROOT, [Requirement::new(1, ..=1), Requirement::new(2, ..)])
+      .with_version((1, 1), [])
+      .with_version((1, 2), [])
+      .with_version((2, 1), [Requirement::at_least(1, 2)]);
+    let message = conflict(registry.solve_minimal(ROOT));
+    assert_eq!(message, "(0, 1) depends on 1 at versions 0 to 1, but (2, 1) needs at least (1, 2).");
+    let missing = Registry::new().with_version(ROOT, [Requirement::at_least(4, 1)]);
+    assert!(conflict(missing.solve_minimal(ROOT)).contains("matches no registered version"));
+    assert!(matches!(registry.solve_minimal((9, 9)), Err(SolveError::UnknownRoot((9, 9)))));
+  }
+}
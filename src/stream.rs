@@ -0,0 +1,330 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs::{self, File},
+  io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use thiserror::Error;
+
+use crate::{DepId, DepMap, DepTree, DepTreeBuilderError};
+
+#[derive(Debug, Error)]
+pub enum StreamError {
+  #[error("adjacency store failed: {0}")]
+  Io(#[from] io::Error),
+  #[error(transparent)]
+  Invalid(#[from] DepTreeBuilderError),
+}
+
+pub type StreamResult<T> = Result<T, StreamError>;
+
+/// Where a `StreamingBuilder` keeps the edges it was fed. `scan` replays
+/// records in the order they were appended.
+pub trait AdjacencyStore {
+  fn append(&mut self, id: DepId, deps: &[DepId]) -> io::Result<()>;
+  fn scan(&mut self, visit: &mut dyn FnMut(DepId, &[DepId])) -> io::Result<()>;
+
+  /// Replays only the records of `units`. The default filters a full `scan`;
+  /// stores that can look records up should override it.
+  fn scan_units(&mut self, units: &BTreeSet<DepId>, visit: &mut dyn FnMut(DepId, &[DepId])) -> io::Result<()> {
+    self.scan(&mut |id, deps| {
+      if units.contains(&id) {
+        visit(id, deps);
+      }
+    })
+  }
+}
+
+/// Keeps every record on the heap, for graphs that fit after all.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+  records: Vec<(DepId, Vec<DepId>)>,
+  index: BTreeMap<DepId, Vec<usize>>,
+}
+
+impl AdjacencyStore for MemoryStore {
+  fn append(&mut self, id: DepId, deps: &[DepId]) -> io::Result<()> {
+    self.index.entry(id).or_default().push(self.records.len());
+    self.records.push((id, deps.to_vec()));
+    Ok(())
+  }
+
+  fn scan(&mut self, visit: &mut dyn FnMut(DepId, &[DepId])) -> io::Result<()> {
+    for (id, deps) in &self.records {
+      visit(*id, deps);
+    }
+    Ok(())
+  }
+
+  fn scan_units(&mut self, units: &BTreeSet<DepId>, visit: &mut dyn FnMut(DepId, &[DepId])) -> io::Result<()> {
+    for &record in units.iter().filter_map(|id| self.index.get(id)).flatten() {
+      let (id, deps) = &self.records[record];
+      visit(*id, deps);
+    }
+    Ok(())
+  }
+}
+
+/// Spills records to a temporary file, removed again on drop. The byte
+/// offset of every record stays on the heap so `scan_units` can seek to it.
+#[derive(Debug)]
+pub struct FileStore {
+  path: PathBuf,
+  writer: BufWriter<File>,
+  len: u64,
+  offsets: BTreeMap<DepId, Vec<u64>>,
+}
+
+impl FileStore {
+  /// Creates a fresh file in the system temporary directory.
+  pub fn new() -> io::Result<Self> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!("dep-tree-{}-{}.edges", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+    Self::create(std::env::temp_dir().join(name))
+  }
+
+  /// Creates or truncates the file at `path`.
+  pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+    let path = path.into();
+    let writer = BufWriter::new(File::create(&path)?);
+    Ok(Self { path, writer, len: 0, offsets: BTreeMap::new() })
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl Drop for FileStore {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+  }
+}
+
+impl AdjacencyStore for FileStore {
+  fn append(&mut self, id: DepId, deps: &[DepId]) -> io::Result<()> {
+    write_unit(&mut self.writer, id)?;
+    self.writer.write_all(&(deps.len() as u64).to_le_bytes())?;
+    for &dep in deps {
+      write_unit(&mut self.writer, dep)?;
+    }
+    self.offsets.entry(id).or_default().push(self.len);
+    self.len += 24 + 16 * deps.len() as u64;
+    Ok(())
+  }
+
+  fn scan(&mut self, visit: &mut dyn FnMut(DepId, &[DepId])) -> io::Result<()> {
+    self.writer.flush()?;
+    let mut reader = BufReader::new(File::open(&self.path)?);
+    let mut deps = Vec::new();
+    while let Some(id) = read_record(&mut reader, &mut deps)? {
+      visit(id, &deps);
+    }
+    Ok(())
+  }
+
+  fn scan_units(&mut self, units: &BTreeSet<DepId>, visit: &mut dyn FnMut(DepId, &[DepId])) -> io::Result<()> {
+    self.writer.flush()?;
+    let mut offsets = units.iter().filter_map(|id| self.offsets.get(id)).flatten().copied().collect::<Vec<_>>();
+    offsets.sort_unstable();
+    let mut reader = BufReader::new(File::open(&self.path)?);
+    let mut deps = Vec::new();
+    for offset in offsets {
+      reader.seek(SeekFrom::Start(offset))?;
+      let id = read_record(&mut reader, &mut deps)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+      visit(id, &deps);
+    }
+    Ok(())
+  }
+}
+
+/// Next record into `deps`, returning its unit, or `None` at a clean end of
+/// input.
+fn read_record(reader: &mut impl Read, deps: &mut Vec<DepId>) -> io::Result<Option<DepId>> {
+  let Some(id) = read_unit(reader)? else {
+    return Ok(None);
+  };
+  let len = read_u64(reader)?;
+  deps.clear();
+  for _ in 0..len {
+    deps.push(read_unit(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?);
+  }
+  Ok(Some(id))
+}
+
+fn write_unit(writer: &mut impl Write, (id, version): DepId) -> io::Result<()> {
+  writer.write_all(&id.to_le_bytes())?;
+  writer.write_all(&(version as u64).to_le_bytes())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+  let mut bytes = [0; 8];
+  reader.read_exact(&mut bytes)?;
+  Ok(u64::from_le_bytes(bytes))
+}
+
+/// Next unit, or `None` at a clean end of input.
+fn read_unit(reader: &mut impl Read) -> io::Result<Option<DepId>> {
+  let mut bytes = [0; 8];
+  match reader.read_exact(&mut bytes) {
+    Ok(()) => {}
+    Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+    Err(error) => return Err(error),
+  }
+  Ok(Some((u64::from_le_bytes(bytes), read_u64(reader)? as usize)))
+}
+
+/// Builder for graphs too large to validate in memory. Units are appended to
+/// an `AdjacencyStore` as they arrive, and `validate` peels the graph reading
+/// each unit's records back once, keeping only per-unit counters on the heap.
+/// Only the cyclic remainder, if any, is loaded to report the cycle.
+#[derive(Debug)]
+pub struct StreamingBuilder<S = FileStore> {
+  store: S,
+  self_dependencies: BTreeSet<DepId>,
+  edges: u64,
+}
+
+impl StreamingBuilder<FileStore> {
+  /// Streaming builder spilling to a `FileStore` in the temporary directory.
+  pub fn new() -> io::Result<Self> {
+    Ok(Self::with_store(FileStore::new()?))
+  }
+}
+
+impl<S: AdjacencyStore> StreamingBuilder<S> {
+  pub fn with_store(store: S) -> Self {
+    Self { store, self_dependencies: BTreeSet::new(), edges: 0 }
+  }
+
+  /// Appends `id` with `deps`, like `DepTreeBuilder::with_dep`.
+  pub fn push(&mut self, id: DepId, deps: &[DepId]) -> io::Result<()> {
+    if deps.contains(&id) {
+      self.self_dependencies.insert(id);
+    }
+    self.edges += deps.len() as u64;
+    self.store.append(id, deps)
+  }
+
+  pub fn extend(&mut self, units: impl IntoIterator<Item = (DepId, Vec<DepId>)>) -> io::Result<()> {
+    for (id, deps) in units {
+      self.push(id, &deps)?;
+    }
+    Ok(())
+  }
+
+  /// Edges pushed so far.
+  pub fn edge_count(&self) -> u64 {
+    self.edges
+  }
+
+  /// Checks the streamed graph for self-dependencies and cycles, reporting
+  /// them like `DepTreeBuilder::build` with one cycle at most.
+  pub fn validate(&mut self) -> StreamResult<()> {
+    let mut errors = self
+      .self_dependencies
+      .iter()
+      .map(|&id| DepTreeBuilderError::SelfDependency(id))
+      .collect::<Vec<_>>();
+    if let Some(cycle) = self.find_cycle()? {
+      errors.push(DepTreeBuilderError::circular(&cycle));
+    }
+    match errors.len() {
+      0 => Ok(()),
+      1 => Err(errors.pop().unwrap().into()),
+      _ => Err(DepTreeBuilderError::Multiple(errors).into()),
+    }
+  }
+
+  /// Peels the graph from the roots by counting remaining dependents (Kahn's
+  /// algorithm). After one full scan for the counts, each round reads back
+  /// only the records of the units freed by the round before. A cyclic
+  /// remainder is loaded into memory to pick a cycle out of it.
+  fn find_cycle(&mut self) -> io::Result<Option<Vec<DepId>>> {
+    let mut dependents = BTreeMap::<DepId, usize>::new();
+    self.store.scan(&mut |id, deps| {
+      dependents.entry(id).or_default();
+      for &dep in deps.iter().filter(|&&dep| dep != id) {
+        *dependents.entry(dep).or_default() += 1;
+      }
+    })?;
+
+    let mut freed = dependents.iter().filter(|(_, &count)| count == 0).map(|(&id, _)| id).collect::<BTreeSet<_>>();
+    let mut peeled = freed.len();
+    while !freed.is_empty() {
+      let mut next = BTreeSet::new();
+      self.store.scan_units(&freed, &mut |id, deps| {
+        for &dep in deps.iter().filter(|&&dep| dep != id) {
+          let count = dependents.get_mut(&dep).unwrap();
+          *count -= 1;
+          if *count == 0 {
+            next.insert(dep);
+          }
+        }
+      })?;
+      peeled += next.len();
+      freed = next;
+    }
+    if peeled == dependents.len() {
+      return Ok(None);
+    }
+    let remainder = dependents.into_iter().filter(|&(_, count)| count > 0).map(|(id, _)| id).collect::<BTreeSet<_>>();
+
+    let mut graph = DepMap::new();
+    self.store.scan(&mut |id, deps| {
+      if remainder.contains(&id) {
+        let list = graph.entry(id).or_default();
+        list.extend(deps.iter().filter(|&&dep| dep != id && remainder.contains(&dep)));
+      }
+    })?;
+    Ok(Some(cycle_in(graph)))
+  }
+
+  /// Validates, then loads the whole graph into a `DepTree`.
+  pub fn build(mut self) -> StreamResult<DepTree> {
+    self.validate()?;
+    let mut map = DepMap::new();
+    self.store.scan(&mut |id, deps| map.entry(id).or_default().extend_from_slice(deps))?;
+    Ok(DepTree::from_map(&map))
+  }
+
+  pub fn into_store(self) -> S {
+    self.store
+  }
+}
+
+/// Some cycle of a graph known to have one: trims units without remaining
+/// dependencies until every unit left has one, then follows dependencies
+/// until a unit repeats.
+fn cycle_in(mut graph: DepMap) -> Vec<DepId> {
+  let mut dependents = DepMap::new();
+  for (&id, deps) in &graph {
+    for &dep in deps {
+      dependents.entry(dep).or_default().push(id);
+    }
+  }
+  let mut leaves = graph.iter().filter(|(_, deps)| deps.is_empty()).map(|(&id, _)| id).collect::<Vec<_>>();
+  let mut remaining = graph.iter().map(|(&id, deps)| (id, deps.len())).collect::<BTreeMap<_, _>>();
+  while let Some(leaf) = leaves.pop() {
+    graph.remove(&leaf);
+    for dependent in dependents.remove(&leaf).unwrap_or_default() {
+      let count = remaining.get_mut(&dependent).unwrap();
+      *count -= 1;
+      if *count == 0 {
+        leaves.push(dependent);
+      }
+    }
+  }
+
+  let mut path = Vec::new();
+  let mut seen = BTreeMap::new();
+  let mut id = *graph.keys().next().unwrap();
+  while !seen.contains_key(&id) {
+    seen.insert(id, path.len());
+    path.push(id);
+    id = *graph[&id].iter().find(|dep| graph.contains_key(dep)).unwrap();
+  }
+  path.split_off(seen[&id])
+}
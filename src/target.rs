@@ -0,0 +1,85 @@
+use std::{fmt, rc::Rc};
+
+use crate::{DepId, DepTree, DepTreeBuilder};
+
+/// Condition on the target platform an edge applies to, see
+/// `DepTreeBuilder::for_target`.
+#[derive(Clone)]
+pub enum TargetExpr {
+  /// Holds for the target with exactly this name, e.g. `linux`.
+  Name(String),
+  /// Holds for every target the predicate accepts.
+  Predicate(Rc<dyn Fn(&str) -> bool>),
+}
+
+impl TargetExpr {
+  pub fn predicate(predicate: impl Fn(&str) -> bool + 'static) -> Self {
+    Self::Predicate(Rc::new(predicate))
+  }
+
+  pub fn matches(&self, target: &str) -> bool {
+    match self {
+      Self::Name(name) => name == target,
+      Self::Predicate(predicate) => predicate(target),
+    }
+  }
+}
+
+impl fmt::Debug for TargetExpr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Name(name) => f.debug_tuple("Name").field(name).finish(),
+      Self::Predicate(_) => f.write_str("Predicate"),
+    }
+  }
+}
+
+impl From<&str> for TargetExpr {
+  fn from(name: &str) -> Self {
+    Self::Name(name.to_owned())
+  }
+}
+
+impl From<String> for TargetExpr {
+  fn from(name: String) -> Self {
+    Self::Name(name)
+  }
+}
+
+impl DepTreeBuilder {
+  /// Adds the `id -> dep` edge unless it is already there and makes it
+  /// conditional on `target`. An edge given several conditions applies to
+  /// every target matching one of them; edges without conditions apply to
+  /// all targets. See `DepTree::resolve_for`.
+  pub fn for_target(&mut self, id: DepId, dep: DepId, target: impl Into<TargetExpr>) -> Self {
+    let exists = self.inner.try_borrow().unwrap().get(&id).is_some_and(|deps| deps.contains(&dep));
+    if !exists {
+      self.with_dep(id, vec![dep]);
+    }
+    self.edges.try_borrow_mut().unwrap().targets.entry((id, dep)).or_default().push(target.into());
+    self.clone()
+  }
+}
+
+impl DepTree {
+  /// Tree for one platform: every edge without a condition plus the
+  /// conditional edges whose conditions match `target`. Predicates run now,
+  /// once per conditional edge.
+  pub fn resolve_for(&self, target: &str) -> Self {
+    let applies = |id: DepId, dep: DepId| {
+      self.edges.targets.get(&(id, dep)).is_none_or(|exprs| exprs.iter().any(|expr| expr.matches(target)))
+    };
+    let inner = self
+      .inner
+      .iter()
+      .map(|(&id, deps)| {
+        if deps.iter().all(|&dep| applies(id, dep)) {
+          (id, deps.clone())
+        } else {
+          (id, deps.iter().copied().filter(|&dep| applies(id, dep)).collect())
+        }
+      })
+      .collect();
+    self.derive(inner, self.edges.targets.keys().map(|&(_, dep)| dep))
+  }
+}
@@ -0,0 +1,133 @@
+/// Just enough XML for the GraphML importer: elements, attributes and text,
+/// skipping declarations, comments and doctypes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Event {
+  Start { name: String, attributes: Vec<(String, String)>, empty: bool },
+  End { name: String },
+  Text(String),
+}
+
+impl Event {
+  pub fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+  }
+}
+
+/// Element name without its namespace prefix.
+pub(crate) fn local_name(name: &str) -> &str {
+  name.rsplit(':').next().unwrap_or(name)
+}
+
+pub(crate) fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// Splits `text` into events, failing with the byte offset of the first error.
+pub(crate) fn parse(text: &str) -> Result<Vec<Event>, (usize, String)> {
+  let mut events = Vec::new();
+  let mut position = 0;
+  while position < text.len() {
+    let rest = &text[position..];
+    if !rest.starts_with('<') {
+      let end = rest.find('<').unwrap_or(rest.len());
+      let raw = &rest[..end];
+      if !raw.trim().is_empty() {
+        events.push(Event::Text(unescape(raw).map_err(|e| (position, e))?));
+      }
+      position += end;
+      continue;
+    }
+    let skip = [("<?", "?>"), ("<!--", "-->"), ("<!DOCTYPE", ">")];
+    if let Some((_, close)) = skip.iter().find(|(open, _)| rest.starts_with(open)) {
+      let end = rest.find(close).ok_or((position, format!("missing `{close}`")))?;
+      position += end + close.len();
+      continue;
+    }
+    if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+      let end = cdata.find("]]>").ok_or((position, "missing `]]>`".to_owned()))?;
+      events.push(Event::Text(cdata[..end].to_owned()));
+      position += "<![CDATA[".len() + end + 3;
+      continue;
+    }
+    let end = tag_end(rest).ok_or((position, "unterminated tag".to_owned()))?;
+    let tag = &rest[1..end];
+    position += end + 1;
+    if let Some(name) = tag.strip_prefix('/') {
+      events.push(Event::End { name: name.trim().to_owned() });
+      continue;
+    }
+    let (tag, empty) = match tag.strip_suffix('/') {
+      Some(tag) => (tag, true),
+      None => (tag, false),
+    };
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = tag[..name_end].to_owned();
+    let attributes = attributes(&tag[name_end..]).map_err(|e| (position, e))?;
+    events.push(Event::Start { name, attributes, empty });
+  }
+  Ok(events)
+}
+
+/// Index of the `>` closing the tag at the start of `text`, skipping quoted
+/// attribute values.
+fn tag_end(text: &str) -> Option<usize> {
+  let mut quote = None;
+  for (i, c) in text.char_indices() {
+    match (quote, c) {
+      (None, '"' | '\'') => quote = Some(c),
+      (Some(q), c) if c == q => quote = None,
+      (None, '>') => return Some(i),
+      _ => {}
+    }
+  }
+  None
+}
+
+fn attributes(mut text: &str) -> Result<Vec<(String, String)>, String> {
+  let mut attributes = Vec::new();
+  loop {
+    text = text.trim_start();
+    if text.is_empty() {
+      return Ok(attributes);
+    }
+    let (key, rest) = text.split_once('=').ok_or("attribute without value")?;
+    let rest = rest.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'');
+    let quote = quote.ok_or("unquoted attribute value")?;
+    let value_end = rest[1..].find(quote).ok_or("unterminated attribute value")?;
+    attributes.push((key.trim().to_owned(), unescape(&rest[1..1 + value_end])?));
+    text = &rest[value_end + 2..];
+  }
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+  let mut out = String::new();
+  let mut rest = text;
+  while let Some(start) = rest.find('&') {
+    out.push_str(&rest[..start]);
+    let end = rest[start..].find(';').ok_or("unterminated entity")? + start;
+    let entity = &rest[start + 1..end];
+    let decoded = match entity {
+      "lt" => '<',
+      "gt" => '>',
+      "amp" => '&',
+      "quot" => '"',
+      "apos" => '\'',
+      _ => {
+        let code = match entity.strip_prefix("#x") {
+          Some(hex) => u32::from_str_radix(hex, 16).ok(),
+          None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+        };
+        code.and_then(char::from_u32).ok_or(format!("unknown entity `&{entity};`"))?
+      }
+    };
+    out.push(decoded);
+    rest = &rest[end + 1..];
+  }
+  out.push_str(rest);
+  Ok(out)
+}